@@ -36,7 +36,7 @@ fn main() {
     } else if args[0] == "publish" {
         doxidize::ops::publish(&config, &log).expect("could not publish docs");
     } else if args[0] == "serve" {
-        doxidize::ops::serve(&config, &log).expect("could not serve docs");
+        doxidize::ops::serve(&config, &log, false).expect("could not serve docs");
     } else {
         panic!("incorrect command {}", args[0]);
     }
@@ -1,10 +1,9 @@
 //! Functions for retrieving package data from `cargo`.
 
 use analysis_data::config::Config as AnalysisConfig;
-use slog::Logger;
 use serde_json;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 
 use Config;
@@ -35,6 +34,14 @@ pub struct Target {
     ///
     /// [`crate_name`]: ./struct.Target.html#method.crate_name
     pub name: String,
+
+    /// The name of the package the target is part of.
+    ///
+    /// In a workspace, this is how cargo knows which member we mean.
+    pub package: String,
+
+    /// The path to the root source file of the target, like `src/lib.rs`.
+    pub src_path: PathBuf,
 }
 
 impl Target {
@@ -92,6 +99,8 @@ pub fn generate_analysis(config: &Config, target: &Target) -> Result<()> {
         .stderr(Stdio::piped())
         .stdout(Stdio::null());
 
+    command.args(&["--package", &target.package]);
+
     match target.kind {
         TargetKind::Library => {
             command.arg("--lib");
@@ -114,83 +123,164 @@ pub fn generate_analysis(config: &Config, target: &Target) -> Result<()> {
     Ok(())
 }
 
-/// Parse the library target from the crate metadata.
-pub fn target_from_metadata(log: &Logger, metadata: &serde_json::Value) -> Result<Target> {
-    // We can expect at least one package and target, otherwise the metadata generation would have
-    // failed.
-    let targets = metadata["packages"][0]["targets"]
+/// Returns the name the documentation site should have.
+///
+/// This is the name of the package at `manifest_path`, or, for a virtual workspace, the name of the
+/// workspace's directory.
+pub fn site_name(metadata: &serde_json::Value, manifest_path: &Path) -> Result<String> {
+    let packages = metadata["packages"]
         .as_array()
-        .expect("`targets` is not an array");
-
-    let mut targets = targets
-        .into_iter()
-        .flat_map(|target| {
-            let name = target["name"].as_str().expect("`name` is not a string");
-            let kinds = target["kind"].as_array().expect("`kind` is not an array");
-
-            if kinds.len() != 1 {
-                return Some(Err(error::Json {
-                    location: format!("expected one kind for target '{}'", name),
-                }.into()));
-            }
+        .expect("`packages` is not an array");
 
-            let kind = match kinds[0].as_str().unwrap() {
-                "lib" => TargetKind::Library,
-                "bin" => TargetKind::Binary,
-                _ => return None,
-            };
+    let package = if packages.len() == 1 {
+        packages.first()
+    } else {
+        packages.iter().find(|package| {
+            package["manifest_path"].as_str().map(Path::new) == Some(manifest_path)
+        })
+    };
 
-            let target = Target {
-                name: name.to_owned(),
-                kind,
-            };
+    if let Some(name) = package.and_then(|package| package["name"].as_str()) {
+        return Ok(name.to_string());
+    }
 
-            Some(Ok(target))
+    metadata["workspace_root"]
+        .as_str()
+        .and_then(|root| Path::new(root).file_name())
+        .and_then(|name| name.to_str())
+        .map(|name| name.to_string())
+        .ok_or_else(|| {
+            error::Json {
+                location: String::from("expected a package name or a workspace root"),
+            }.into()
         })
-        .collect::<Result<Vec<_>>>()?;
+}
 
-    if targets.is_empty() {
-        Err(failure::err_msg("no targets with supported kinds (`bin`, `lib`) found"))
-    } else if targets.len() == 1 {
-        Ok(targets.remove(0))
-    } else {
-        // FIXME(#105): Handle more than one target.
-        let (mut libs, mut bins): (Vec<_>, Vec<_>) =
-            targets.into_iter().partition(|target| match target.kind {
-                TargetKind::Library => true,
-                TargetKind::Binary => false,
-            });
-
-        // Default to documenting the library if it exists.
-        let target = if !libs.is_empty() {
-            libs.remove(0)
-        } else {
-            bins.remove(0)
-        };
+/// Parse the targets to document from the crate metadata.
+///
+/// This covers the package at `manifest_path`. For the root of a workspace, or a virtual manifest,
+/// that's the targets of every member instead.
+///
+/// If `selected` is given, only targets with those names are returned, and it's an error for one of
+/// them not to exist. Otherwise every `lib` and `bin` target is. Libraries always come first.
+pub fn targets_from_metadata(
+    metadata: &serde_json::Value,
+    manifest_path: &Path,
+    selected: Option<&[String]>,
+) -> Result<Vec<Target>> {
+    // We can expect at least one package and target, otherwise the metadata generation would have
+    // failed. In a workspace, there's one package for each member, even when it's run in a member.
+    let packages = metadata["packages"]
+        .as_array()
+        .expect("`packages` is not an array");
 
-        let kind = match target.kind {
-            TargetKind::Library => "library",
-            TargetKind::Binary => "first binary",
-        };
+    let is_workspace_root =
+        metadata["workspace_root"].as_str().map(Path::new) == manifest_path.parent();
 
-        warn!(
-            log,
-            "Found more than one target to document. Documenting the {}: {}", kind, target.name
-        );
+    let member = packages.iter().find(|package| {
+        package["manifest_path"].as_str().map(Path::new) == Some(manifest_path)
+    });
 
-        Ok(target)
+    let packages: Vec<&serde_json::Value> = match member {
+        Some(member) if !is_workspace_root => vec![member],
+        _ => packages.iter().collect(),
+    };
+
+    let mut targets = Vec::new();
+
+    for package in packages {
+        let package_name = package["name"]
+            .as_str()
+            .expect("`name` is not a string");
+
+        let package_targets = package["targets"]
+            .as_array()
+            .expect("`targets` is not an array");
+
+        let package_targets = package_targets
+            .into_iter()
+            .flat_map(|target| {
+                let name = target["name"].as_str().expect("`name` is not a string");
+                let kinds = target["kind"].as_array().expect("`kind` is not an array");
+
+                if kinds.len() != 1 {
+                    return Some(Err(error::Json {
+                        location: format!("expected one kind for target '{}'", name),
+                    }.into()));
+                }
+
+                let kind = match kinds[0].as_str().unwrap() {
+                    "lib" => TargetKind::Library,
+                    "bin" => TargetKind::Binary,
+                    _ => return None,
+                };
+
+                let src_path = target["src_path"].as_str().unwrap_or_default();
+
+                let target = Target {
+                    name: name.to_owned(),
+                    kind,
+                    package: package_name.to_owned(),
+                    src_path: PathBuf::from(src_path),
+                };
+
+                Some(Ok(target))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        targets.extend(package_targets);
     }
+
+    let targets = match selected {
+        Some(selected) => {
+            if let Some(missing) = selected
+                .iter()
+                .find(|name| !targets.iter().any(|target| &target.name == *name))
+            {
+                bail!("`{}` is not a `bin` or `lib` target of this package", missing);
+            }
+
+            targets
+                .into_iter()
+                .filter(|target| selected.contains(&target.name))
+                .collect()
+        }
+        None => targets,
+    };
+
+    if targets.is_empty() {
+        return Err(failure::err_msg(
+            "no targets with supported kinds (`bin`, `lib`) found",
+        ));
+    }
+
+    let (mut libs, bins): (Vec<_>, Vec<_>) =
+        targets.into_iter().partition(|target| match target.kind {
+            TargetKind::Library => true,
+            TargetKind::Binary => false,
+        });
+
+    libs.extend(bins);
+
+    Ok(libs)
 }
 
 #[cfg(test)]
 mod tests {
-    use slog;
+    use std::path::{Path, PathBuf};
+
     use super::{Target, TargetKind};
 
-    #[test]
-    fn target_from_metadata() {
-        let log = slog::Logger::root(slog::Discard, o!());
+    /// Parse the targets of a package that isn't in a workspace.
+    fn targets(
+        metadata: &::serde_json::Value,
+        selected: Option<&[String]>,
+    ) -> ::Result<Vec<Target>> {
+        super::targets_from_metadata(metadata, Path::new("/package/Cargo.toml"), selected)
+    }
 
+    #[test]
+    fn targets_from_metadata() {
         // work around until https://github.com/rust-lang-nursery/rustfmt/issues/2344 is fixed
         #[cfg_attr(rustfmt, rustfmt_skip)]
         let metadata = json!({
@@ -206,12 +296,14 @@ mod tests {
             },
             ],
         });
-        let target = super::target_from_metadata(&log, &metadata).unwrap();
+        let target = targets(&metadata, None).unwrap().remove(0);
         assert_eq!(
             target,
             Target {
                 kind: TargetKind::Library,
                 name: "underscored_name".into(),
+                package: "underscored_name".into(),
+                src_path: PathBuf::new(),
             }
         );
         assert_eq!(&target.crate_name(), "underscored_name");
@@ -231,12 +323,14 @@ mod tests {
             },
             ],
         });
-        let target = super::target_from_metadata(&log, &metadata).unwrap();
+        let target = targets(&metadata, None).unwrap().remove(0);
         assert_eq!(
             target,
             Target {
                 kind: TargetKind::Library,
                 name: "dashed-name".into(),
+                package: "dashed-name".into(),
+                src_path: PathBuf::new(),
             }
         );
         assert_eq!(&target.crate_name(), "dashed_name");
@@ -256,12 +350,14 @@ mod tests {
             },
             ],
         });
-        let target = super::target_from_metadata(&log, &metadata).unwrap();
+        let target = targets(&metadata, None).unwrap().remove(0);
         assert_eq!(
             target,
             Target {
                 kind: TargetKind::Binary,
                 name: "underscored_name".into(),
+                package: "underscored_name".into(),
+                src_path: PathBuf::new(),
             }
         );
         assert_eq!(&target.crate_name(), "underscored_name");
@@ -282,7 +378,7 @@ mod tests {
             ],
         });
         assert_eq!(
-            super::target_from_metadata(&log, &metadata).unwrap().kind,
+            targets(&metadata, None).unwrap()[0].kind,
             TargetKind::Library
         );
 
@@ -302,7 +398,7 @@ mod tests {
             ],
         });
         assert_eq!(
-            super::target_from_metadata(&log, &metadata).unwrap().kind,
+            targets(&metadata, None).unwrap()[0].kind,
             TargetKind::Binary
         );
 
@@ -326,8 +422,127 @@ mod tests {
             ],
         });
         assert_eq!(
-            super::target_from_metadata(&log, &metadata).unwrap().kind,
+            targets(&metadata, None).unwrap()[0].kind,
             TargetKind::Library
         );
     }
+
+    #[test]
+    fn targets_from_metadata_with_several_targets() {
+        // work around until https://github.com/rust-lang-nursery/rustfmt/issues/2344 is fixed
+        #[cfg_attr(rustfmt, rustfmt_skip)]
+        let metadata = json!({
+            "packages": [
+            {
+                "name": "several",
+                "targets": [
+                {
+                    "kind": [ "bin" ],
+                    "name": "first-bin",
+                    "src_path": "/several/src/bin/first-bin.rs",
+                },
+                {
+                    "kind": [ "lib" ],
+                    "name": "several",
+                    "src_path": "/several/src/lib.rs",
+                },
+                {
+                    "kind": [ "bin" ],
+                    "name": "second-bin",
+                    "src_path": "/several/src/bin/second-bin.rs",
+                },
+                ],
+            },
+            ],
+        });
+
+        let targets = targets(&metadata, None).unwrap();
+        let names: Vec<_> = targets.iter().map(|target| target.name.as_str()).collect();
+        assert_eq!(names, vec!["several", "first-bin", "second-bin"]);
+        assert_eq!(targets[0].src_path, PathBuf::from("/several/src/lib.rs"));
+
+        let selected = vec![String::from("second-bin")];
+        let targets = targets(&metadata, Some(&selected)).unwrap();
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].name, "second-bin");
+
+        let selected = vec![String::from("missing")];
+        assert!(targets(&metadata, Some(&selected)).is_err());
+    }
+
+    #[test]
+    fn targets_from_metadata_in_a_workspace() {
+        // work around until https://github.com/rust-lang-nursery/rustfmt/issues/2344 is fixed
+        #[cfg_attr(rustfmt, rustfmt_skip)]
+        let metadata = json!({
+            "packages": [
+            {
+                "name": "cli",
+                "manifest_path": "/workspace/cli/Cargo.toml",
+                "targets": [
+                {
+                    "kind": [ "bin" ],
+                    "name": "cli",
+                },
+                ],
+            },
+            {
+                "name": "core",
+                "manifest_path": "/workspace/core/Cargo.toml",
+                "targets": [
+                {
+                    "kind": [ "lib" ],
+                    "name": "core",
+                },
+                ],
+            },
+            ],
+            "workspace_root": "/workspace",
+        });
+
+        let names = |manifest_path: &str| -> Vec<(String, String)> {
+            super::targets_from_metadata(&metadata, Path::new(manifest_path), None)
+                .unwrap()
+                .into_iter()
+                .map(|target| (target.package, target.name))
+                .collect()
+        };
+
+        let all = vec![
+            (String::from("core"), String::from("core")),
+            (String::from("cli"), String::from("cli")),
+        ];
+        assert_eq!(names("/workspace/Cargo.toml"), all);
+
+        // running in a member only documents that member
+        assert_eq!(
+            names("/workspace/cli/Cargo.toml"),
+            vec![(String::from("cli"), String::from("cli"))]
+        );
+    }
+
+    #[test]
+    fn site_name() {
+        // work around until https://github.com/rust-lang-nursery/rustfmt/issues/2344 is fixed
+        #[cfg_attr(rustfmt, rustfmt_skip)]
+        let metadata = json!({
+            "packages": [
+            {
+                "name": "cli",
+                "manifest_path": "/workspace/cli/Cargo.toml",
+            },
+            {
+                "name": "core",
+                "manifest_path": "/workspace/core/Cargo.toml",
+            },
+            ],
+            "workspace_root": "/workspace",
+        });
+
+        let member = Path::new("/workspace/core/Cargo.toml");
+        assert_eq!(super::site_name(&metadata, member).unwrap(), "core");
+
+        let root = Path::new("/workspace/Cargo.toml");
+        assert_eq!(super::site_name(&metadata, root).unwrap(), "workspace");
+    }
 }
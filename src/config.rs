@@ -1,14 +1,19 @@
 use analysis;
 use handlebars::{self, Handlebars};
+use num_cpus;
 use serde::Deserializer;
-use toml_edit;
+use toml;
 
+use std::cmp;
+use std::collections::BTreeMap;
 use std::default::Default;
 use std::env;
 use std::fs::{self, File};
-use std::path::{Path, PathBuf};
+use std::io;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 
+use error;
 use Result;
 
 /// A structure that contains various fields that hold data in order to generate doc output.
@@ -21,48 +26,214 @@ pub struct Config {
     /// Path to place rustdoc output
     output_path: Option<PathBuf>,
 
+    /// Path to the markdown sources of the docs, if they're not in `docs`
+    markdown_path: Option<PathBuf>,
+
     /// Contains the Cargo analysis output for the crate being documented
     #[serde(deserialize_with = "deserialize_host")]
     host: analysis::AnalysisHost,
 
     base_url: String,
 
+    /// The title of the site; the name of the crate is used if this isn't set
+    title: Option<String>,
+
+    /// Path to a stylesheet to add to every page
+    theme_path: Option<PathBuf>,
+
+    /// The names of the targets to generate API docs for; all of them if this isn't set
+    api_targets: Option<Vec<String>>,
+
     #[serde(deserialize_with = "deserialize_handlebars")]
     handlebars: Handlebars,
+
+    /// The file each template was loaded from, by name
+    template_paths: BTreeMap<String, PathBuf>,
+
+    /// Problems with `Doxidize.toml` that aren't bad enough to stop us
+    warnings: Vec<String>,
+
+    /// How many pages to render at once; one per CPU if this isn't set
+    jobs: Option<usize>,
+
+    /// The address that `serve` listens on, if it's not the default
+    serve_host: Option<String>,
+
+    /// The port that `serve` tries first, if it's not the default
+    serve_port: Option<u16>,
 }
 
 impl Default for Config {
     fn default() -> Config {
         let manifest_path = PathBuf::from("Cargo.toml");
-        let host = analysis::AnalysisHost::new(analysis::Target::Debug);
 
-        let config_path = PathBuf::from("Doxidize.toml");
-        let mut contents = String::new();
+        // there's nowhere to report problems from here, so a broken `Doxidize.toml` is ignored
+        Config::load(manifest_path.clone()).unwrap_or_else(|_| {
+            Config::with_settings(
+                manifest_path,
+                DoxidizeToml::default(),
+                default_handlebars(),
+                Vec::new(),
+            )
+        })
+    }
+}
 
-        let base_url = (|| {
-            let mut toml_file = File::open(config_path)?;
-            toml_file.read_to_string(&mut contents)?;
-            let doc = contents.parse::<toml_edit::Document>()?;
+/// The settings in `Doxidize.toml`. All of them are optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct DoxidizeToml {
+    docs: DocsSettings,
+    api: ApiSettings,
+    serve: ServeSettings,
 
-            Ok((|| {
-                let value = doc["docs"]["base-url"].as_value()?;
-                let value = value.as_str()?;
-                Some(value.to_string())
-            })()
-                .ok_or("")?)
-        })()
-            .unwrap_or_else(|_: Box<::std::error::Error>| String::from(""));
+    /// Paths to templates to use instead of the built-in ones, by name
+    templates: BTreeMap<String, PathBuf>,
+}
 
-        let handlebars = default_handlebars();
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct DocsSettings {
+    base_url: String,
+    source: Option<PathBuf>,
+    output: Option<PathBuf>,
+    title: Option<String>,
+    theme: Option<PathBuf>,
+}
 
-        Config {
-            manifest_path,
-            host,
-            output_path: None,
-            base_url,
-            handlebars,
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ApiSettings {
+    targets: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ServeSettings {
+    host: Option<String>,
+    port: Option<u16>,
+}
+
+/// Parse the contents of `Doxidize.toml`, returning the settings and a warning for each key we
+/// don't know about.
+fn parse_doxidize_toml(
+    config_path: &Path,
+    contents: &str,
+) -> ::std::result::Result<(DoxidizeToml, Vec<String>), error::DoxidizeToml> {
+    let problem = |line, message| error::DoxidizeToml {
+        path: config_path.to_path_buf(),
+        line,
+        message,
+    };
+
+    // syntax errors know where they are, but errors about the type of a value only know the key
+    let value = toml::from_str::<toml::Value>(contents)
+        .map_err(|e| problem(e.line_col().map(|(line, _)| line + 1), e.to_string()))?;
+
+    let settings = toml::from_str::<DoxidizeToml>(contents).map_err(|e| {
+        let line = error_key(&e.to_string()).and_then(|key| {
+            let key: Vec<&str> = key.split('.').collect();
+            key_line(contents, &key)
+        });
+
+        problem(line, e.to_string())
+    })?;
+
+    let warnings = unknown_keys(&value)
+        .iter()
+        .map(|key| {
+            let key: Vec<&str> = key.iter().map(|segment| &segment[..]).collect();
+            let location = match key_line(contents, &key) {
+                Some(line) => format!("{}:{}", config_path.display(), line),
+                None => config_path.display().to_string(),
+            };
+
+            format!("{}: unknown key `{}`", location, key.join("."))
+        })
+        .collect();
+
+    Ok((settings, warnings))
+}
+
+/// Find every key in `Doxidize.toml` that isn't one of our settings.
+fn unknown_keys(value: &toml::Value) -> Vec<Vec<String>> {
+    let mut unknown = Vec::new();
+
+    let table = match value.as_table() {
+        Some(table) => table,
+        None => return unknown,
+    };
+
+    for (name, value) in table {
+        let known: &[&str] = match &name[..] {
+            "docs" => &["base-url", "source", "output", "title", "theme"],
+            "api" => &["targets"],
+            "serve" => &["host", "port"],
+            // any template can be overridden, and the names are checked when they're registered
+            "templates" => continue,
+            _ => {
+                unknown.push(vec![name.clone()]);
+                continue;
+            }
+        };
+
+        // if this isn't a table, deserializing will have already failed
+        if let Some(section) = value.as_table() {
+            for key in section.keys() {
+                if !known.contains(&&key[..]) {
+                    unknown.push(vec![name.clone(), key.clone()]);
+                }
+            }
         }
     }
+
+    unknown
+}
+
+/// Pull the key out of a message like "invalid type: integer `5`, expected a string for key
+/// `docs.base-url`".
+fn error_key(message: &str) -> Option<&str> {
+    let start = message.rfind("for key `")? + "for key `".len();
+    let length = message[start..].find('`')?;
+
+    Some(&message[start..start + length])
+}
+
+/// Find the line, starting at one, that defines a key, given the names of the tables it's in.
+fn key_line(contents: &str, key: &[&str]) -> Option<usize> {
+    // the key may itself be a table
+    let header = format!("[{}]", key.join("."));
+    if let Some(index) = contents.lines().position(|line| line.trim() == header) {
+        return Some(index + 1);
+    }
+
+    let (name, tables) = key.split_last()?;
+    let mut lines = contents.lines().enumerate();
+
+    if !tables.is_empty() {
+        let header = format!("[{}]", tables.join("."));
+        lines.by_ref().find(|&(_, line)| line.trim() == header)?;
+    }
+
+    lines
+        .find(|&(_, line)| defines_key(line, name))
+        .map(|(index, _)| index + 1)
+}
+
+/// Does this line assign a value to the key `name`?
+fn defines_key(line: &str, name: &str) -> bool {
+    let line = line.trim_left();
+    let quoted = format!("\"{}\"", name);
+
+    let rest = if line.starts_with(name) {
+        &line[name.len()..]
+    } else if line.starts_with(&quoted) {
+        &line[quoted.len()..]
+    } else {
+        return false;
+    };
+
+    rest.trim_left().starts_with('=')
 }
 
 fn deserialize_host<'de, D>(_: D) -> ::std::result::Result<analysis::AnalysisHost, D::Error>
@@ -79,29 +250,47 @@ where
     Ok(default_handlebars())
 }
 
+/// The templates that come with Doxidize, by name, relative to the root of this crate.
+const DEFAULT_TEMPLATES: &[(&str, &str)] = &[
+    ("example", "templates/markdown/example.hbs"),
+    ("page", "templates/html/page.hbs"),
+    ("api", "templates/markdown/api.hbs"),
+    ("api-index", "templates/markdown/api-index.hbs"),
+    ("mod", "templates/markdown/mod.hbs"),
+    ("struct", "templates/markdown/struct.hbs"),
+    ("enum", "templates/markdown/enum.hbs"),
+    ("trait", "templates/markdown/trait.hbs"),
+    ("function", "templates/markdown/function.hbs"),
+    ("type", "templates/markdown/type.hbs"),
+    ("static", "templates/markdown/static.hbs"),
+    ("const", "templates/markdown/const.hbs"),
+    ("impls", "templates/markdown/impls.hbs"),
+];
+
+fn default_template_paths() -> BTreeMap<String, PathBuf> {
+    DEFAULT_TEMPLATES
+        .iter()
+        .map(|&(name, path)| {
+            let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(path);
+            (name.to_string(), path)
+        })
+        .collect()
+}
+
 fn default_handlebars() -> Handlebars {
     let mut handlebars = Handlebars::new();
 
-    macro_rules! template {
-        ($handlebars:ident, $name:expr, $path:expr) => {
-            $handlebars
-                .register_template_file($name, concat!(env!("CARGO_MANIFEST_DIR"), "/", $path))
-                .expect(concat!("Failed to register template ", $name, " (", $path, ")"));
-        };
+    for (name, path) in default_template_paths() {
+        if let Err(e) = handlebars.register_template_file(&name, &path) {
+            panic!(
+                "Failed to register template {} ({}): {}",
+                name,
+                path.display(),
+                e
+            );
+        }
     }
 
-    template!(handlebars, "example", "templates/markdown/example.hbs");
-    template!(handlebars, "page", "templates/html/page.hbs");
-    template!(handlebars, "api", "templates/markdown/api.hbs");
-    template!(handlebars, "mod", "templates/markdown/mod.hbs");
-    template!(handlebars, "struct", "templates/markdown/struct.hbs");
-    template!(handlebars, "enum", "templates/markdown/enum.hbs");
-    template!(handlebars, "trait", "templates/markdown/trait.hbs");
-    template!(handlebars, "function", "templates/markdown/function.hbs");
-    template!(handlebars, "type", "templates/markdown/type.hbs");
-    template!(handlebars, "static", "templates/markdown/static.hbs");
-    template!(handlebars, "const", "templates/markdown/const.hbs");
-
     handlebars.register_helper(
         "up-dir",
         Box::new(
@@ -137,36 +326,87 @@ impl Config {
             manifest_path
         };
 
-        let host = analysis::AnalysisHost::new(analysis::Target::Debug);
+        Config::load(manifest_path)
+    }
+
+    /// Create a `Config` from the `Doxidize.toml` next to `manifest_path`, if there is one.
+    fn load(manifest_path: PathBuf) -> Result<Config> {
+        let root_path = manifest_path.parent().unwrap().to_path_buf();
+        let config_path = root_path.join("Doxidize.toml");
 
-        let config_path = manifest_path.parent().unwrap().join("Doxidize.toml");
         let mut contents = String::new();
 
-        let base_url = (|| {
-            let mut toml_file = File::open(config_path)?;
-            toml_file.read_to_string(&mut contents)?;
-            let doc = contents.parse::<toml_edit::Document>()?;
+        match File::open(&config_path) {
+            Ok(mut toml_file) => {
+                toml_file.read_to_string(&mut contents)?;
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e.into()),
+        }
+
+        let (settings, warnings) = parse_doxidize_toml(&config_path, &contents)?;
 
-            Ok((|| {
-                let value = doc["docs"]["base-url"].as_value()?;
-                let value = value.as_str()?;
-                Some(value.to_string())
-            })()
-                .ok_or("")?)
-        })()
-            .unwrap_or_else(|_: Box<::std::error::Error>| String::from(""));
+        let mut handlebars = default_handlebars();
 
-        let handlebars = default_handlebars();
+        for (name, path) in &settings.templates {
+            let problem = |message| error::DoxidizeToml {
+                path: config_path.clone(),
+                line: key_line(&contents, &["templates", &name[..]]),
+                message,
+            };
 
-        let config = Config {
+            if handlebars.get_template(name).is_none() {
+                return Err(problem(format!("there is no template named `{}`", name)).into());
+            }
+
+            handlebars
+                .register_template_file(name, root_path.join(path))
+                .map_err(|e| problem(format!("couldn't load template `{}`: {}", name, e)))?;
+        }
+
+        Ok(Config::with_settings(
             manifest_path,
-            host,
-            output_path: None,
-            base_url,
+            settings,
             handlebars,
-        };
+            warnings,
+        ))
+    }
 
-        Ok(config)
+    fn with_settings(
+        manifest_path: PathBuf,
+        settings: DoxidizeToml,
+        handlebars: Handlebars,
+        warnings: Vec<String>,
+    ) -> Config {
+        let root_path = manifest_path.parent().unwrap().to_path_buf();
+        let DoxidizeToml {
+            docs,
+            api,
+            serve,
+            templates,
+        } = settings;
+
+        let mut template_paths = default_template_paths();
+        for (name, path) in templates {
+            template_paths.insert(name, root_path.join(path));
+        }
+
+        Config {
+            manifest_path,
+            host: analysis::AnalysisHost::new(analysis::Target::Debug),
+            output_path: docs.output.map(|path| root_path.join(path)),
+            markdown_path: docs.source.map(|path| root_path.join(path)),
+            base_url: docs.base_url,
+            title: docs.title,
+            theme_path: docs.theme.map(|path| root_path.join(path)),
+            api_targets: api.targets,
+            handlebars,
+            template_paths,
+            warnings,
+            jobs: None,
+            serve_host: serve.host,
+            serve_port: serve.port,
+        }
     }
 
     /// Returns the directory containing the `Cargo.toml` of the crate being documented.
@@ -196,10 +436,55 @@ impl Config {
         self.output_path = Some(output_path)
     }
 
+    /// Set the directory containing the markdown sources of the docs
+    pub fn set_markdown_path(&mut self, markdown_path: PathBuf) {
+        self.markdown_path = Some(markdown_path)
+    }
+
+    /// Returns how many pages to render at once
+    pub fn jobs(&self) -> usize {
+        cmp::max(1, self.jobs.unwrap_or_else(num_cpus::get))
+    }
+
+    /// Set how many pages to render at once
+    pub fn set_jobs(&mut self, jobs: usize) {
+        self.jobs = Some(jobs)
+    }
+
+    /// Returns the address that `serve` listens on
+    pub fn serve_host(&self) -> &str {
+        match self.serve_host {
+            Some(ref host) => host,
+            None => "127.0.0.1",
+        }
+    }
+
+    /// Returns the port that `serve` tries first
+    pub fn serve_port(&self) -> u16 {
+        self.serve_port.unwrap_or(7878)
+    }
+
+    pub fn set_serve_host(&mut self, host: String) {
+        self.serve_host = Some(host)
+    }
+
+    pub fn set_serve_port(&mut self, port: u16) {
+        self.serve_port = Some(port)
+    }
+
     pub fn readme_path(&self) -> PathBuf {
         self.markdown_path().join("README.md")
     }
 
+    /// Returns the path of the file that records what the last build did, so the next one can
+    /// skip pages that haven't changed.
+    pub fn build_cache_path(&self) -> PathBuf {
+        self.root_path()
+            .join("target")
+            .join("doxidize")
+            .join("build-cache.json")
+    }
+
     pub fn rls_target_path(&self) -> PathBuf {
         self.root_path().join("target").join("rls")
     }
@@ -212,14 +497,23 @@ impl Config {
         self.markdown_path().join("examples")
     }
 
+    /// Returns the directory containing the markdown sources of the docs
     pub fn markdown_path(&self) -> PathBuf {
-        self.root_path().join("docs")
+        match self.markdown_path {
+            Some(ref path) => path.clone(),
+            None => self.root_path().join("docs"),
+        }
     }
 
     pub fn api_markdown_path(&self) -> PathBuf {
         self.markdown_path().join("api")
     }
 
+    /// Where `api-diff` writes its report, when asked to
+    pub fn api_changes_path(&self) -> PathBuf {
+        self.markdown_path().join("api-changes.md")
+    }
+
     pub fn api_readme_path(&self) -> PathBuf {
         self.api_markdown_path().join("README.md")
     }
@@ -252,7 +546,137 @@ impl Config {
         &self.base_url
     }
 
+    /// Returns the title of the site, if `Doxidize.toml` sets one.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_ref().map(|title| &title[..])
+    }
+
+    /// Returns the path to the stylesheet that's added to every page, if there is one.
+    pub fn theme_path(&self) -> Option<&Path> {
+        self.theme_path.as_ref().map(|path| path.as_path())
+    }
+
+    /// Returns the names of the targets chosen in `Doxidize.toml`, if any were.
+    pub fn api_targets(&self) -> Option<&[String]> {
+        self.api_targets.as_ref().map(|targets| &targets[..])
+    }
+
     pub fn handlebars(&self) -> &Handlebars {
         &self.handlebars
     }
+
+    /// Returns the path of the file that the template with this name was loaded from.
+    pub fn template_path(&self, name: &str) -> Option<&Path> {
+        self.template_paths.get(name).map(|path| path.as_path())
+    }
+
+    /// Returns any problems with `Doxidize.toml` that should be reported, like unknown keys.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{error_key, key_line, parse_doxidize_toml};
+
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn parses_every_setting() {
+        let contents = r#"[docs]
+base-url = "foo"
+source = "guide"
+output = "public"
+title = "Foo"
+theme = "theme.css"
+
+[api]
+targets = ["foo"]
+
+[serve]
+host = "0.0.0.0"
+port = 8000
+
+[templates]
+page = "templates/page.hbs"
+"#;
+
+        let (settings, warnings) =
+            parse_doxidize_toml(Path::new("Doxidize.toml"), contents).unwrap();
+
+        assert!(warnings.is_empty());
+        assert_eq!(settings.docs.base_url, "foo");
+        assert_eq!(settings.docs.source, Some(PathBuf::from("guide")));
+        assert_eq!(settings.docs.output, Some(PathBuf::from("public")));
+        assert_eq!(settings.docs.title, Some(String::from("Foo")));
+        assert_eq!(settings.docs.theme, Some(PathBuf::from("theme.css")));
+        assert_eq!(settings.api.targets, Some(vec![String::from("foo")]));
+        assert_eq!(settings.serve.host, Some(String::from("0.0.0.0")));
+        assert_eq!(settings.serve.port, Some(8000));
+        assert_eq!(
+            settings.templates.get("page"),
+            Some(&PathBuf::from("templates/page.hbs"))
+        );
+    }
+
+    #[test]
+    fn empty_file() {
+        let (settings, warnings) = parse_doxidize_toml(Path::new("Doxidize.toml"), "").unwrap();
+
+        assert!(warnings.is_empty());
+        assert_eq!(settings.docs.base_url, "");
+        assert_eq!(settings.api.targets, None);
+    }
+
+    #[test]
+    fn warns_about_unknown_keys() {
+        let contents = "[docs]\nbase_url = \"foo\"\n\n[doc]\ntitle = \"Foo\"\n";
+
+        let (_, warnings) = parse_doxidize_toml(Path::new("Doxidize.toml"), contents).unwrap();
+
+        assert_eq!(
+            warnings,
+            vec![
+                String::from("Doxidize.toml:4: unknown key `doc`"),
+                String::from("Doxidize.toml:2: unknown key `docs.base_url`"),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_the_line_of_a_bad_value() {
+        let contents = "[api]\ntargets = [\"foo\"]\n\n[docs]\nbase-url = 5\n";
+
+        let error = parse_doxidize_toml(Path::new("Doxidize.toml"), contents).unwrap_err();
+
+        assert_eq!(error.line, Some(5));
+    }
+
+    #[test]
+    fn reports_the_line_of_a_syntax_error() {
+        let contents = "[docs]\nbase-url = \"foo\"\ntitle = \n";
+
+        let error = parse_doxidize_toml(Path::new("Doxidize.toml"), contents).unwrap_err();
+
+        assert_eq!(error.line, Some(3));
+    }
+
+    #[test]
+    fn finds_keys() {
+        let contents = "title = \"top\"\n\n[docs]\n\"title\" = \"Foo\"\n";
+
+        assert_eq!(key_line(contents, &["docs", "title"]), Some(4));
+        assert_eq!(key_line(contents, &["docs"]), Some(3));
+        assert_eq!(key_line(contents, &["api", "targets"]), None);
+    }
+
+    #[test]
+    fn finds_the_key_in_an_error() {
+        assert_eq!(
+            error_key("invalid type: integer `5`, expected a string for key `docs.base-url`"),
+            Some("docs.base-url")
+        );
+        assert_eq!(error_key("expected an equals, found a newline"), None);
+    }
 }
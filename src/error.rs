@@ -29,3 +29,245 @@ pub struct Json {
 #[derive(Debug, Fail)]
 #[fail(display = "Project is not initialized. Try `doxidize init`")]
 pub struct UninitializedProject;
+
+/// A problem with `Doxidize.toml`.
+#[derive(Debug, Fail)]
+pub struct DoxidizeToml {
+    /// The path to `Doxidize.toml`
+    pub path: ::std::path::PathBuf,
+    /// The line the problem is on, starting at one, if we know it
+    pub line: Option<usize>,
+    /// What was wrong
+    pub message: String,
+}
+
+impl ::std::fmt::Display for DoxidizeToml {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+/// A problem with the front matter of a markdown file.
+#[derive(Debug, Fail)]
+pub struct FrontMatter {
+    /// The markdown file with the problem
+    pub path: ::std::path::PathBuf,
+    /// The line the problem is on, starting at one
+    pub line: usize,
+    /// What was wrong
+    pub message: String,
+}
+
+// paths don't implement Display, so we can't derive this
+impl ::std::fmt::Display for FrontMatter {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+    }
+}
+
+/// Thrown when building if any markdown files have problems with their front matter.
+///
+/// We check every file before failing, so that they can all be fixed at once.
+#[derive(Debug, Fail)]
+pub struct FrontMatterErrors {
+    /// Every problem we found
+    pub errors: Vec<FrontMatter>,
+}
+
+impl ::std::fmt::Display for FrontMatterErrors {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "found {} problem(s) with front matter:", self.errors.len())?;
+
+        for error in &self.errors {
+            write!(f, "\n  {}", error)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Thrown when building if `Menu.toml` has a problem.
+#[derive(Debug, Fail)]
+pub struct Menu {
+    /// The path to `Menu.toml`
+    pub path: ::std::path::PathBuf,
+    /// The line the problem is on, starting at one, if we know it
+    pub line: Option<usize>,
+    /// What was wrong
+    pub message: String,
+}
+
+impl ::std::fmt::Display for Menu {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+/// Thrown when building if `Menu.toml` has any problems.
+///
+/// Like with front matter, we report every problem at once.
+#[derive(Debug, Fail)]
+pub struct MenuErrors {
+    /// Every problem we found
+    pub errors: Vec<Menu>,
+}
+
+impl ::std::fmt::Display for MenuErrors {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "found {} problem(s) with Menu.toml:", self.errors.len())?;
+
+        for error in &self.errors {
+            write!(f, "\n  {}", error)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An intra-doc link to an API item that doesn't exist.
+#[derive(Debug, Fail)]
+pub struct BrokenLink {
+    /// The markdown file with the link
+    pub path: ::std::path::PathBuf,
+    /// The line the link is on, starting at one
+    pub line: usize,
+    /// What was wrong
+    pub message: String,
+}
+
+impl ::std::fmt::Display for BrokenLink {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+    }
+}
+
+/// Thrown when building if any intra-doc links can't be resolved.
+#[derive(Debug, Fail)]
+pub struct BrokenLinks {
+    /// Every broken link we found
+    pub errors: Vec<BrokenLink>,
+}
+
+impl ::std::fmt::Display for BrokenLinks {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "found {} broken link(s):", self.errors.len())?;
+
+        for error in &self.errors {
+            write!(f, "\n  {}", error)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A link in the generated docs that doesn't lead anywhere.
+#[derive(Debug, Fail)]
+pub struct DeadLink {
+    /// The markdown file the link is in, or the page, if it wasn't rendered from markdown
+    pub source: ::std::path::PathBuf,
+    /// Where the link points
+    pub href: String,
+    /// Why it doesn't lead anywhere
+    pub problem: String,
+}
+
+impl ::std::fmt::Display for DeadLink {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(
+            f,
+            "{}: the link to `{}` is broken; {}",
+            self.source.display(),
+            self.href,
+            self.problem
+        )
+    }
+}
+
+/// Thrown when checking links if any of them are dead.
+#[derive(Debug, Fail)]
+pub struct DeadLinks {
+    /// Every dead link we found
+    pub errors: Vec<DeadLink>,
+}
+
+impl ::std::fmt::Display for DeadLinks {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "found {} dead link(s):", self.errors.len())?;
+
+        for error in &self.errors {
+            write!(f, "\n  {}", error)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A generated section of a markdown file that couldn't be generated again.
+#[derive(Debug, Fail)]
+pub struct GeneratedConflict {
+    /// The markdown file
+    pub path: ::std::path::PathBuf,
+    /// Which section couldn't be generated again, and why
+    pub problem: String,
+}
+
+impl ::std::fmt::Display for GeneratedConflict {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.problem)
+    }
+}
+
+/// Thrown when updating if any generated sections have been edited by hand.
+///
+/// Everything else is still updated; these are left as they were.
+#[derive(Debug, Fail)]
+pub struct GeneratedConflicts {
+    /// Every section that was left alone
+    pub errors: Vec<GeneratedConflict>,
+}
+
+impl ::std::fmt::Display for GeneratedConflicts {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(
+            f,
+            "{} generated section(s) could not be updated:",
+            self.errors.len()
+        )?;
+
+        for error in &self.errors {
+            write!(f, "\n  {}", error)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Thrown by `doxidize update --dry-run` if updating would change anything.
+#[derive(Debug, Fail)]
+#[fail(
+    display = "{} API markdown file(s) are out of date. Run `doxidize update` to update them",
+    changes
+)]
+pub struct OutOfDate {
+    /// How many files and directories would be created, modified or deleted
+    pub changes: usize,
+}
+
+/// Thrown by `doxidize coverage --fail-under` if too little of the API is documented.
+#[derive(Debug, Fail)]
+#[fail(
+    display = "only {:.1}% of public items are documented, which is under the {}% required",
+    coverage, threshold
+)]
+pub struct CoverageTooLow {
+    /// The percentage of public items that are documented
+    pub coverage: f64,
+    /// The percentage that was required
+    pub threshold: f64,
+}
@@ -9,6 +9,7 @@
 pub mod nested_module {
     /// A nested struct
     pub struct NestedStruct {
+        /// the only field you can see
         pub x: i32,
         _y: i32,
     }
@@ -44,7 +45,9 @@ pub const ALWAYS_FIVE: Option<i32> = Some(5);
 
 /// A point in space
 pub struct Point {
+    /// the horizontal position
     pub x: i32,
+    /// the vertical position
     pub y: i32,
 }
 
@@ -63,8 +66,11 @@ impl Point {
 
 /// A traffic light
 pub enum TrafficLight {
+    /// stop!
     Red,
+    /// slow down
     Yellow,
+    /// go
     Green,
 }
 
@@ -72,4 +78,10 @@ pub enum TrafficLight {
 pub trait Speak {
     /// Actually do the speech!
     fn speak(&self);
+
+    /// Say it again, for emphasis
+    fn speak_twice(&self) {
+        self.speak();
+        self.speak();
+    }
 }
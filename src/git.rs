@@ -1,5 +1,5 @@
 use std::fmt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::{self, Command};
 
 use Result;
@@ -42,17 +42,12 @@ pub fn init(git_dir: &Path) -> Result<()> {
     Ok(())
 }
 
-pub fn initialize_remote(git_dir: &Path, remote_name: &str, has_base_url: bool) -> Result<()> {
-    let dot_dot = if has_base_url {
-        "../../../../.git"
-    } else {
-        "../../../.git"
-    };
-
+pub fn initialize_remote(git_dir: &Path, remote_name: &str, remote: &Path) -> Result<()> {
     let output = Command::new("git")
         .arg("-C")
         .arg(git_dir.as_os_str())
-        .args(&["remote", "add", remote_name, dot_dot])
+        .args(&["remote", "add", remote_name])
+        .arg(remote.as_os_str())
         .output()
         .expect("failed to execute git init");
 
@@ -81,6 +76,39 @@ pub fn reset_to_remote_head(git_dir: &Path, remote_name: &str) -> Result<()> {
         }.into());
     }
 
+    // nothing has been published yet
+    let pages = format!("{}/gh-pages", remote_name);
+    let published = Command::new("git")
+        .arg("-C")
+        .arg(git_dir.as_os_str())
+        .args(&["rev-parse", "--verify", "--quiet"])
+        .arg(&pages)
+        .output()
+        .expect("failed to execute git rev-parse")
+        .status
+        .success();
+
+    if !published {
+        return Ok(());
+    }
+
+    // only move the branch, so that the next commit has whatever's there now on top of what was
+    // published before
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(git_dir.as_os_str())
+        .args(&["reset", "--soft"])
+        .arg(&pages)
+        .output()
+        .expect("failed to execute git reset");
+
+    if !output.status.success() {
+        return Err(GitFailure {
+            command_name: "git reset",
+            output,
+        }.into());
+    }
+
     Ok(())
 }
 
@@ -180,3 +208,82 @@ pub fn push(git_dir: &Path) -> Result<()> {
 
     Ok(())
 }
+
+/// Returns the top directory of the repository that `git_dir` is in.
+pub fn show_toplevel(git_dir: &Path) -> Result<PathBuf> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(git_dir.as_os_str())
+        .args(&["rev-parse", "--show-toplevel"])
+        .output()
+        .expect("failed to execute git rev-parse");
+
+    if !output.status.success() {
+        return Err(GitFailure {
+            command_name: "git rev-parse",
+            output,
+        }.into());
+    }
+
+    Ok(PathBuf::from(String::from_utf8(output.stdout)?.trim()))
+}
+
+/// Returns the path of `git_dir` relative to the top of its repository, like `crates/foo/`.
+pub fn show_prefix(git_dir: &Path) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(git_dir.as_os_str())
+        .args(&["rev-parse", "--show-prefix"])
+        .output()
+        .expect("failed to execute git rev-parse");
+
+    if !output.status.success() {
+        return Err(GitFailure {
+            command_name: "git rev-parse",
+            output,
+        }.into());
+    }
+
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+/// Check out `revision` into a new worktree at `path`, without creating a branch for it.
+pub fn add_worktree(git_dir: &Path, path: &Path, revision: &str) -> Result<()> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(git_dir.as_os_str())
+        .args(&["worktree", "add", "--detach"])
+        .arg(path.as_os_str())
+        .arg(revision)
+        .output()
+        .expect("failed to execute git worktree add");
+
+    if !output.status.success() {
+        return Err(GitFailure {
+            command_name: "git worktree add",
+            output,
+        }.into());
+    }
+
+    Ok(())
+}
+
+/// Remove a worktree that `add_worktree` made, along with anything that was built in it.
+pub fn remove_worktree(git_dir: &Path, path: &Path) -> Result<()> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(git_dir.as_os_str())
+        .args(&["worktree", "remove", "--force"])
+        .arg(path.as_os_str())
+        .output()
+        .expect("failed to execute git worktree remove");
+
+    if !output.status.success() {
+        return Err(GitFailure {
+            command_name: "git worktree remove",
+            output,
+        }.into());
+    }
+
+    Ok(())
+}
@@ -12,6 +12,7 @@ extern crate failure;
 extern crate failure_derive;
 extern crate handlebars;
 extern crate notify;
+extern crate num_cpus;
 extern crate rls_analysis as analysis;
 extern crate rls_data as analysis_data;
 extern crate serde;
@@ -25,6 +26,8 @@ extern crate slog;
 #[macro_use]
 extern crate slugify;
 extern crate remove_dir_all;
+extern crate scoped_threadpool;
+extern crate toml;
 extern crate toml_edit;
 extern crate walkdir;
 
@@ -34,6 +37,7 @@ mod error;
 pub mod examples;
 mod git;
 pub mod ops;
+mod save_analysis;
 
 pub use config::Config;
 
@@ -11,12 +11,14 @@ extern crate structopt;
 #[macro_use]
 extern crate structopt_derive;
 
+use std::env;
 use std::path::PathBuf;
 
 use failure::Error;
 use slog::Drain;
 use structopt::StructOpt;
 
+use doxidize::ops::{ApiDiffOptions, CoverageOptions, UpdateOptions};
 use doxidize::Config;
 
 #[derive(StructOpt, Debug)]
@@ -30,37 +32,158 @@ struct Opt {
                 default_value = "./Cargo.toml",
                 parse(from_os_str))]
     manifest_path: PathBuf,
+
+    #[structopt(long = "docs-dir",
+                help = "The directory containing the markdown sources of the docs",
+                parse(from_os_str))]
+    docs_dir: Option<PathBuf>,
+
+    #[structopt(long = "output",
+                help = "The directory to write the rendered docs to",
+                parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    #[structopt(long = "jobs",
+                short = "j",
+                help = "How many pages to render at once; defaults to the number of CPUs")]
+    jobs: Option<usize>,
 }
 
 #[derive(StructOpt, Debug)]
 enum Command {
+    #[structopt(name = "api-diff",
+                about = "Show how the public API changed between two git revisions")]
+    ApiDiff {
+        #[structopt(help = "The older revision, like a tag for the last release")]
+        old: String,
+
+        #[structopt(help = "The newer revision", default_value = "HEAD")]
+        new: String,
+
+        #[structopt(long = "write-page",
+                    help = "Also write the changes into docs/api-changes.md")]
+        write_page: bool,
+    },
     #[structopt(name = "build")]
-    Build,
+    Build {
+        #[structopt(long = "check-links", help = "Check that every link in the built docs works")]
+        check_links: bool,
+    },
+    #[structopt(name = "check", about = "Check that every link in the built docs works")]
+    Check,
     #[structopt(name = "clean")]
     Clean,
+    #[structopt(name = "coverage", about = "Report which public items are missing docs")]
+    Coverage {
+        #[structopt(long = "json", help = "Print the report as json")]
+        json: bool,
+
+        #[structopt(long = "fail-under",
+                    help = "Fail if less than this percentage of public items are documented")]
+        fail_under: Option<f64>,
+    },
     #[structopt(name = "publish")]
     Publish,
     #[structopt(name = "serve", about = "Serve documentation on a local HTTP server")]
-    Serve,
+    Serve {
+        #[structopt(long = "host", help = "The address to listen on; defaults to 127.0.0.1")]
+        host: Option<String>,
+
+        #[structopt(long = "port",
+                    help = "The port to listen on, or to start looking for a free one from; defaults to 7878")]
+        port: Option<u16>,
+
+        #[structopt(long = "watch-src",
+                    help = "Regenerate the API docs when the crate's sources or examples change")]
+        watch_src: bool,
+    },
     #[structopt(name = "init")]
     Init,
     #[structopt(name = "update")]
-    Update,
+    Update {
+        #[structopt(long = "dry-run",
+                    help = "Show what would change, without changing anything, and fail if anything would")]
+        dry_run: bool,
+
+        #[structopt(long = "adopt",
+                    help = "Replace files without a generated section with newly generated ones")]
+        adopt: bool,
+    },
 }
 
 fn run(opts: Opt, log: &slog::Logger) -> Result<(), Error> {
-    let config = Config::new(opts.manifest_path)?;
+    let mut config = Config::new(opts.manifest_path)?;
+
+    // these are relative to where we're run from, unlike the paths in Doxidize.toml
+    let current_dir = env::current_dir()?;
+
+    if let Some(docs_dir) = opts.docs_dir {
+        config.set_markdown_path(current_dir.join(docs_dir));
+    }
+
+    if let Some(output) = opts.output {
+        config.set_output_path(current_dir.join(output));
+    }
+
+    if let Some(jobs) = opts.jobs {
+        config.set_jobs(jobs);
+    }
 
     info!(log, "doxidizing `{}`", config.root_path().display());
 
+    for warning in config.warnings() {
+        warn!(log, "{}", warning);
+    }
+
     if let Some(command) = opts.command {
         match command {
-            Command::Build => doxidize::ops::build(&config, &log),
+            Command::ApiDiff {
+                old,
+                new,
+                write_page,
+            } => {
+                let options = ApiDiffOptions {
+                    old,
+                    new,
+                    write_page,
+                };
+
+                doxidize::ops::api_diff(&config, &log, &options)
+            }
+            Command::Build { check_links } => {
+                doxidize::ops::build(&config, &log)?;
+
+                if check_links {
+                    doxidize::ops::check(&config, &log)
+                } else {
+                    Ok(())
+                }
+            }
+            Command::Check => doxidize::ops::check(&config, &log),
             Command::Clean => doxidize::ops::clean(&config, &log),
+            Command::Coverage { json, fail_under } => {
+                doxidize::ops::coverage(&config, &log, &CoverageOptions { json, fail_under })
+            }
             Command::Publish => doxidize::ops::publish(&config, &log),
-            Command::Serve => doxidize::ops::serve(&config, &log),
+            Command::Serve {
+                host,
+                port,
+                watch_src,
+            } => {
+                if let Some(host) = host {
+                    config.set_serve_host(host);
+                }
+
+                if let Some(port) = port {
+                    config.set_serve_port(port);
+                }
+
+                doxidize::ops::serve(&config, &log, watch_src)
+            }
             Command::Init => doxidize::ops::init(&config, &log),
-            Command::Update => doxidize::ops::update(&config, &log),
+            Command::Update { dry_run, adopt } => {
+                doxidize::ops::update(&config, &log, &UpdateOptions { dry_run, adopt })
+            }
         }
     } else {
         doxidize::ops::init(&config, &log)
@@ -0,0 +1,404 @@
+//! Comparing the public API of two revisions of a crate.
+//!
+//! Each revision is checked out into a git worktree of its own, and analyzed the same way as when
+//! generating the API docs. The public items, down to the fields, variants and methods of each
+//! type, are then matched up by their qualnames, and their signatures compared.
+
+use slog::Logger;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+
+use cargo;
+use git;
+use ops::cache;
+use ops::generated::GeneratedFiles;
+use ops::init::api;
+use Config;
+use Result;
+
+/// A public item, as it is in one revision.
+#[derive(Clone, Debug, PartialEq)]
+struct ApiItem {
+    kind: &'static str,
+
+    /// The item's declaration, with its whitespace tidied up; empty for modules
+    signature: String,
+}
+
+/// How the public API changed from one revision to the other, in order of qualname.
+#[derive(Debug, Default, PartialEq)]
+struct ApiChanges {
+    added: Vec<(String, ApiItem)>,
+    removed: Vec<(String, ApiItem)>,
+    changed: Vec<(String, ApiItem, ApiItem)>,
+}
+
+/// What `api_diff` compares, and what it does with the changes.
+#[derive(Debug)]
+pub struct ApiDiffOptions {
+    /// The older revision, like a tag for the last release
+    pub old: String,
+
+    /// The newer revision
+    pub new: String,
+
+    /// Write the changes into the docs, as well as printing them
+    pub write_page: bool,
+}
+
+/// How many names to try for a worktree's directory before giving up.
+const WORKTREE_ATTEMPTS: u32 = 100;
+
+/// A checkout of one revision, which is removed again once we're done with it.
+struct Worktree<'a> {
+    repository: &'a Path,
+    path: PathBuf,
+    log: Logger,
+}
+
+impl<'a> Drop for Worktree<'a> {
+    fn drop(&mut self) {
+        // there's nothing more we can do than say so, and the system cleans up temp dirs anyway
+        if let Err(e) = git::remove_worktree(self.repository, &self.path) {
+            warn!(self.log, "could not remove the worktree";
+            "dir" => self.path.display(), "error" => e.to_string());
+        }
+    }
+}
+
+pub fn api_diff(config: &Config, log: &Logger, options: &ApiDiffOptions) -> Result<()> {
+    let log = log.new(o!("command" => "api-diff"));
+    info!(log, "starting");
+
+    let old = public_api(config, &log, &options.old)?;
+    let new = public_api(config, &log, &options.new)?;
+
+    let changes = compare(&old, &new);
+
+    print!("{}", render_text(&changes));
+
+    if options.write_page {
+        let page = config.api_changes_path();
+
+        debug!(log, "writing the changes into the docs"; o!("file" => page.display()));
+
+        // it's generated like the API docs, so release notes can be written around it
+        let mut files = GeneratedFiles::new();
+        files.create_dir(&config.markdown_path())?;
+        files.write(&page, &render_markdown(&changes, &options.old, &options.new))?;
+        files.check_conflicts()?;
+    }
+
+    info!(log, "done");
+    Ok(())
+}
+
+/// Analyze the public API of the crate as it is at `revision`.
+fn public_api(config: &Config, log: &Logger, revision: &str) -> Result<BTreeMap<String, ApiItem>> {
+    let log = log.new(o!("revision" => revision.to_string()));
+
+    // the crate may be anywhere in the repository, so we find it again in the worktree
+    let repository = config.root_path();
+    let prefix = git::show_prefix(repository)?;
+    let manifest_name = config.manifest_path().file_name().unwrap();
+
+    let path = worktree_dir()?;
+
+    debug!(log, "checking out revision"; o!("dir" => path.display()));
+    if let Err(e) = git::add_worktree(repository, &path, revision) {
+        // it's still empty, so this is all there is to clean up
+        fs::remove_dir(&path).ok();
+        return Err(e);
+    }
+
+    let worktree = Worktree {
+        repository,
+        path,
+        log: log.clone(),
+    };
+
+    let checkout = Config::new(worktree.path.join(prefix).join(manifest_name))?;
+
+    let metadata = cargo::retrieve_metadata(checkout.manifest_path())?;
+    let targets =
+        cargo::targets_from_metadata(&metadata, checkout.manifest_path(), checkout.api_targets())?;
+
+    api::generate_and_load_analysis(&checkout, &targets, &log)?;
+
+    let mut items = BTreeMap::new();
+
+    for target in &targets {
+        for item in api::public_items(&checkout, target)? {
+            // a module's signature is the file it's in, which doesn't matter to anyone using it
+            let signature = if item.kind == "module" {
+                String::new()
+            } else {
+                item.signature.split_whitespace().collect::<Vec<_>>().join(" ")
+            };
+
+            // libraries come first, so if a binary shares their crate name, the library wins
+            items.entry(item.qualname).or_insert(ApiItem {
+                kind: item.kind,
+                signature,
+            });
+        }
+    }
+
+    Ok(items)
+}
+
+/// Make an empty directory to check a revision out into, with a name that nothing else has,
+/// the way `TempDir` does.
+fn worktree_dir() -> Result<PathBuf> {
+    for attempt in 0..WORKTREE_ATTEMPTS {
+        let name = cache::hash(&(process::id(), SystemTime::now(), attempt));
+        let path = env::temp_dir().join(format!("doxidize-api-diff-{:016x}", name));
+
+        match fs::create_dir(&path) {
+            Ok(()) => return Ok(path),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    bail!(
+        "could not make a directory in {} to check out a revision into",
+        env::temp_dir().display()
+    )
+}
+
+/// Work out which items were added, removed, or had their signature changed.
+fn compare(old: &BTreeMap<String, ApiItem>, new: &BTreeMap<String, ApiItem>) -> ApiChanges {
+    let mut changes = ApiChanges::default();
+
+    for (qualname, new_item) in new {
+        match old.get(qualname) {
+            None => changes.added.push((qualname.clone(), new_item.clone())),
+            Some(old_item) if old_item != new_item => {
+                changes.changed.push((qualname.clone(), old_item.clone(), new_item.clone()))
+            }
+            Some(_) => (),
+        }
+    }
+
+    for (qualname, old_item) in old {
+        if !new.contains_key(qualname) {
+            changes.removed.push((qualname.clone(), old_item.clone()));
+        }
+    }
+
+    changes
+}
+
+/// Describe an item in a single line: its signature, or just its kind if it doesn't have one.
+fn describe(item: &ApiItem) -> &str {
+    if item.signature.is_empty() {
+        item.kind
+    } else {
+        &item.signature
+    }
+}
+
+fn render_text(changes: &ApiChanges) -> String {
+    if changes.added.is_empty() && changes.removed.is_empty() && changes.changed.is_empty() {
+        return String::from("The public API hasn't changed.\n");
+    }
+
+    let mut text = String::new();
+
+    let mut list = |title: &str, items: &[(String, ApiItem)]| {
+        if items.is_empty() {
+            return;
+        }
+
+        text.push_str(&format!("{}:\n", title));
+
+        for &(ref qualname, ref item) in items {
+            text.push_str(&format!("  {} {}\n", item.kind, qualname));
+
+            if !item.signature.is_empty() {
+                text.push_str(&format!("    {}\n", item.signature));
+            }
+        }
+
+        text.push('\n');
+    };
+
+    list("Added", &changes.added);
+    list("Removed", &changes.removed);
+
+    if !changes.changed.is_empty() {
+        text.push_str("Changed:\n");
+
+        for &(ref qualname, ref old, ref new) in &changes.changed {
+            text.push_str(&format!(
+                "  {} {}\n    - {}\n    + {}\n",
+                new.kind,
+                qualname,
+                describe(old),
+                describe(new)
+            ));
+        }
+
+        text.push('\n');
+    }
+
+    // every section ends with a blank line, but the last one doesn't need it
+    text.pop();
+    text
+}
+
+fn render_markdown(changes: &ApiChanges, old_revision: &str, new_revision: &str) -> String {
+    let mut markdown = format!(
+        "---\nid = \"api-changes\"\ntitle = \"API changes\"\n---\n\
+         # API changes\n\nChanges to the public API from `{}` to `{}`.\n",
+        old_revision, new_revision
+    );
+
+    if changes.added.is_empty() && changes.removed.is_empty() && changes.changed.is_empty() {
+        markdown.push_str("\nThe public API hasn't changed.\n");
+        return markdown;
+    }
+
+    let mut list = |title: &str, items: &[(String, ApiItem)]| {
+        if items.is_empty() {
+            return;
+        }
+
+        markdown.push_str(&format!("\n## {}\n\n", title));
+
+        for &(ref qualname, ref item) in items {
+            if item.signature.is_empty() {
+                markdown.push_str(&format!("* {} `{}`\n", item.kind, qualname));
+            } else {
+                markdown.push_str(&format!(
+                    "* {} `{}`: `{}`\n",
+                    item.kind, qualname, item.signature
+                ));
+            }
+        }
+    };
+
+    list("Added", &changes.added);
+    list("Removed", &changes.removed);
+
+    if !changes.changed.is_empty() {
+        markdown.push_str("\n## Changed\n\n");
+
+        for &(ref qualname, ref old, ref new) in &changes.changed {
+            markdown.push_str(&format!(
+                "* {} `{}`, from `{}` to `{}`\n",
+                new.kind,
+                qualname,
+                describe(old),
+                describe(new)
+            ));
+        }
+    }
+
+    markdown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare, render_markdown, render_text, ApiChanges, ApiItem};
+
+    use std::collections::BTreeMap;
+
+    fn item(kind: &'static str, signature: &str) -> ApiItem {
+        ApiItem {
+            kind,
+            signature: signature.to_string(),
+        }
+    }
+
+    fn api(items: &[(&str, ApiItem)]) -> BTreeMap<String, ApiItem> {
+        items
+            .iter()
+            .map(|&(qualname, ref item)| (qualname.to_string(), item.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn finds_added_removed_and_changed_items() {
+        let old = api(&[
+            ("example", item("module", "")),
+            ("example::run", item("function", "fn run()")),
+            ("example::Old", item("struct", "struct Old")),
+        ]);
+        let new = api(&[
+            ("example", item("module", "")),
+            ("example::run", item("function", "fn run(verbose: bool)")),
+            ("example::New", item("struct", "struct New")),
+        ]);
+
+        assert_eq!(
+            compare(&old, &new),
+            ApiChanges {
+                added: vec![(String::from("example::New"), item("struct", "struct New"))],
+                removed: vec![(String::from("example::Old"), item("struct", "struct Old"))],
+                changed: vec![(
+                    String::from("example::run"),
+                    item("function", "fn run()"),
+                    item("function", "fn run(verbose: bool)"),
+                )],
+            }
+        );
+    }
+
+    #[test]
+    fn kind_changes_are_changes() {
+        let old = api(&[("example::Thing", item("struct", "struct Thing"))]);
+        let new = api(&[("example::Thing", item("enum", "enum Thing"))]);
+
+        assert_eq!(compare(&old, &new).changed.len(), 1);
+    }
+
+    #[test]
+    fn renders_changes() {
+        let changes = ApiChanges {
+            added: vec![(String::from("example::New"), item("struct", "struct New"))],
+            removed: Vec::new(),
+            changed: vec![(
+                String::from("example::run"),
+                item("function", "fn run()"),
+                item("function", "fn run(verbose: bool)"),
+            )],
+        };
+
+        assert_eq!(
+            render_text(&changes),
+            "Added:\n  struct example::New\n    struct New\n\n\
+             Changed:\n  function example::run\n    - fn run()\n    + fn run(verbose: bool)\n"
+        );
+
+        let markdown = render_markdown(&changes, "v1", "v2");
+        assert!(markdown.starts_with("---\nid = \"api-changes\""), "{}", markdown);
+        assert!(
+            markdown.contains("## Added\n\n* struct `example::New`: `struct New`\n"),
+            "{}",
+            markdown
+        );
+        assert!(
+            markdown.contains(
+                "## Changed\n\n* function `example::run`, from `fn run()` to `fn run(verbose: bool)`\n"
+            ),
+            "{}",
+            markdown
+        );
+    }
+
+    #[test]
+    fn nothing_changed() {
+        assert_eq!(
+            render_text(&ApiChanges::default()),
+            "The public API hasn't changed.\n"
+        );
+    }
+}
@@ -1,16 +1,25 @@
 use comrak::{self, ComrakOptions};
+use handlebars::Handlebars;
+use scoped_threadpool::Pool;
 use serde_json;
 use slog::Logger;
 use slugify::slugify;
+use toml;
 use toml_edit;
 use walkdir::{DirEntry, WalkDir};
 
+use std::cmp;
 use std::fs::{self, File};
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 
 use cargo;
 use config::Config;
 use error;
+use ops::init::api;
+use ops::intra_doc::{self, ApiIndex};
+use ops::cache::{self, BuildCache};
+use ops::{menu, search};
 use Result;
 
 /// metadata for each file we need to process
@@ -46,7 +55,20 @@ pub fn build(config: &Config, log: &Logger) -> Result<()> {
     }
 
     let metadata = cargo::retrieve_metadata(config.manifest_path())?;
-    let target = cargo::target_from_metadata(&log, &metadata)?;
+    let site_name = match config.title() {
+        Some(title) => title.to_string(),
+        None => cargo::site_name(&metadata, config.manifest_path())?,
+    };
+
+    let theme = match config.theme_path() {
+        Some(path) => {
+            debug!(log, "reading theme"; "path" => path.display());
+            let mut theme = String::new();
+            File::open(path)?.read_to_string(&mut theme)?;
+            Some(theme)
+        }
+        None => None,
+    };
 
     debug!(log, "creating target directory";
     "dir" => target_dir.display());
@@ -59,9 +81,49 @@ pub fn build(config: &Config, log: &Logger) -> Result<()> {
 
     let menu = create_menu(config, &log, &entries)?;
 
+    let targets =
+        cargo::targets_from_metadata(&metadata, config.manifest_path(), config.api_targets())?;
+
+    let api_index = create_api_index(config, &log, &entries, &targets)?;
+
+    // anything that could change every page goes into this hash, so that if any of it changes,
+    // every page is rendered again
+    let page_template = match config.template_path("page") {
+        Some(path) => {
+            let mut page_template = String::new();
+            File::open(path)?.read_to_string(&mut page_template)?;
+            page_template
+        }
+        None => String::new(),
+    };
+
+    let shared_hash = cache::hash(&(
+        env!("CARGO_PKG_VERSION"),
+        serde_json::to_string(&menu)?,
+        &site_name,
+        &theme,
+        &base_url,
+        &target_dir,
+        &api_index,
+        page_template,
+    ));
+
+    let cache_path = config.build_cache_path();
+    debug!(log, "loading build cache"; "file" => cache_path.display());
+    let mut old_cache = BuildCache::load(&cache_path);
+    let mut new_cache = BuildCache::new(shared_hash);
+
+    // reading files and checking the cache is quick, so that happens here, one page at a time.
+    // the pages that need rendering are collected up and rendered in parallel afterwards
+    let mut pages = Vec::new();
+
     for doc_markdown in entries {
         let path = doc_markdown.entry.path();
-        let mut nesting_count = base_nesting_count;
+
+        // how many levels deep are we?
+        let containing_dir = path.parent().expect("somehow this is running at the root");
+        let nesting_count =
+            base_nesting_count + containing_dir.strip_prefix(&docs_dir)?.components().count();
 
         trace!(log, "processing file";
         o!("path" => path.display(), "id" => doc_markdown.id.clone(), "nesting_count" => nesting_count));
@@ -85,61 +147,237 @@ pub fn build(config: &Config, log: &Logger) -> Result<()> {
         trace!(log, "reading file"; "file" => path.display());
         file.read_to_string(&mut contents)?;
 
-        let mut options = ComrakOptions::default();
-        options.default_info_string = Some(String::from("rust"));
-
-        let contents = if &contents[0..3] == "---" {
-            if let Some(index) = contents[3..].find("---") {
-                // three for the offset, four for ---\n
-                &contents[(index + 3 + 4)..]
-            } else {
-                panic!("found opening --- but couldn't find closing ---");
-            }
-        } else {
-            &contents[..]
-        };
-
-        let rendered_contents = comrak::markdown_to_html(contents, &options);
-
         let rendered_path = if file_name == "README.md" {
             new_containing_dir.join("index.html")
         } else {
             new_containing_dir.join(file_name).with_extension("html")
         };
 
-        trace!(log, "rendering to html"; "file" => path.display(), "rendered file" => rendered_path.display());
-        let mut file = File::create(rendered_path)?;
+        let page = path.strip_prefix(&docs_dir)?.to_string_lossy().into_owned();
+        let source_hash = cache::hash(&contents);
 
-        // how many levels deep are we?
-        let containing_dir = path.parent().expect("somehow this is running at the root");
-        let new_containing_dir = containing_dir.strip_prefix(&docs_dir)?;
-        let component_count = new_containing_dir.components().count();
+        if rendered_path.is_file() {
+            if let Some(search_entry) = old_cache.take(shared_hash, &page, source_hash) {
+                trace!(log, "unchanged since the last build, skipping"; "file" => path.display());
+                new_cache.insert(page, source_hash, search_entry);
+                continue;
+            }
+        }
 
-        nesting_count += component_count;
+        pages.push(PageToRender {
+            path: path.to_path_buf(),
+            title: doc_markdown.title,
+            contents,
+            rendered_path,
+            nesting_count,
+            page,
+            source_hash,
+        });
+    }
+
+    let jobs = config.jobs();
+    debug!(log, "rendering pages"; "pages" => pages.len(), "jobs" => jobs);
+
+    let shared = SharedContext {
+        handlebars: config.handlebars(),
+        menu: &menu,
+        api_index: &api_index,
+        target_dir: &target_dir,
+        base_url: &base_url,
+        site_name: &site_name,
+        theme: &theme,
+    };
+
+    // each page gets its own slot for its result, so the results come out in the same order as
+    // the pages, however the threads happen to be scheduled
+    let mut results: Vec<Option<Result<RenderedPage>>> = pages.iter().map(|_| None).collect();
+
+    Pool::new(jobs as u32).scoped(|scope| {
+        for (page, result) in pages.iter().zip(results.iter_mut()) {
+            let shared = &shared;
+            let log = &log;
+
+            scope.execute(move || {
+                *result = Some(render_page(page, shared, log));
+            });
+        }
+    });
+
+    // like with front matter, we report every broken link at once
+    let mut broken_links = Vec::new();
+
+    // warnings are logged here rather than while rendering, so that they come out in page order
+    for (page, result) in pages.into_iter().zip(results) {
+        let rendered = result.expect("every page is rendered before the pool is done")?;
+
+        for link in rendered.broken_links {
+            if link.explicit {
+                broken_links.push(error::BrokenLink {
+                    path: page.path.clone(),
+                    line: link.line,
+                    message: link.message,
+                });
+            } else {
+                warn!(log, "could not resolve link";
+                o!("file" => page.path.display(), "line" => link.line, "problem" => link.message));
+            }
+        }
 
-        trace!(log, "writing rendered file");
+        new_cache.insert(page.page, page.source_hash, rendered.search_entry);
+    }
+
+    if !broken_links.is_empty() {
+        return Err(error::BrokenLinks {
+            errors: broken_links,
+        }.into());
+    }
+
+    // GitHub Pages shows this in place of any page that doesn't exist, and so does `doxidize serve`
+    if !docs_dir.join("404.md").is_file() {
+        let not_found_path = target_dir.join("404.html");
+        debug!(log, "writing not found page"; "file" => not_found_path.display());
+
+        // this is shown at any depth, so its links can't be relative like the other pages' are
+        let root_url = format!("/{}", base_url.trim_left_matches('/'));
+
+        let contents = format!(
+            "<h1>Page not found</h1>\n<p>There's no page here. Try the menu, or go back to the \
+             <a href=\"{}index.html\">front page</a>.</p>\n",
+            root_url
+        );
+
+        let mut file = File::create(&not_found_path)?;
         file.write_all(
             config
                 .handlebars()
                 .render(
                     "page",
                     &json!({
-                        "contents": rendered_contents,
-                        "nest-count": nesting_count,
-                        "base-url": base_url,
+                        "contents": contents,
+                        "nest-count": 0,
+                        "base-url": root_url,
                         "menu": menu,
-                        "title": doc_markdown.title.clone(),
-                        "site-title": target.name.clone()
+                        "title": "Page not found",
+                        "site-title": site_name,
+                        "theme": theme
                     }),
                 )?
                 .as_bytes(),
         )?;
     }
 
+    let search_index_path = target_dir.join("search-index.js");
+    debug!(log, "writing search index"; "file" => search_index_path.display());
+    search::write(&search_index_path, &new_cache.search_entries())?;
+
+    debug!(log, "saving build cache"; "file" => cache_path.display());
+    new_cache.save(&cache_path)?;
+
     info!(log, "done");
     Ok(())
 }
 
+/// A page that has changed since the last build, and so needs rendering
+struct PageToRender {
+    path: PathBuf,
+    title: String,
+    contents: String,
+    rendered_path: PathBuf,
+    nesting_count: usize,
+
+    /// The page's path relative to the docs directory, which is its key in the build cache
+    page: String,
+    source_hash: u64,
+}
+
+/// Everything that rendering a page needs, other than the page itself
+struct SharedContext<'a> {
+    handlebars: &'a Handlebars,
+    menu: &'a [serde_json::Value],
+    api_index: &'a ApiIndex,
+    target_dir: &'a Path,
+    base_url: &'a str,
+    site_name: &'a str,
+    theme: &'a Option<String>,
+}
+
+/// What rendering a page tells us about it
+struct RenderedPage {
+    search_entry: search::Entry,
+    broken_links: Vec<intra_doc::BrokenLink>,
+}
+
+/// Render a single page, and write it out.
+///
+/// This runs on one of the build's threads, so rather than logging problems with the page, it
+/// hands them back, so that they can be reported in order.
+fn render_page(page: &PageToRender, shared: &SharedContext, log: &Logger) -> Result<RenderedPage> {
+    let mut options = ComrakOptions::default();
+    options.default_info_string = Some(String::from("rust"));
+
+    let body = match split_front_matter(&page.contents) {
+        Ok(Some((_, body))) => body,
+        Ok(None) => &page.contents[..],
+        Err((line, message)) => {
+            return Err(error::FrontMatter {
+                path: page.path.clone(),
+                line,
+                message,
+            }.into())
+        }
+    };
+
+    // the body is the end of the file, so everything before it is front matter
+    let first_line = page.contents[..page.contents.len() - body.len()]
+        .matches('\n')
+        .count() + 1;
+
+    // links are relative to the page, in the same way that the templates make them
+    let prefix = format!("{}{}", "../".repeat(page.nesting_count), shared.base_url);
+
+    let (contents, broken_links) =
+        intra_doc::resolve_links(body, shared.api_index, &prefix, first_line);
+
+    let rendered_contents = comrak::markdown_to_html(&contents, &options);
+
+    // urls always use forward slashes, whatever the platform
+    let url = page.rendered_path
+        .strip_prefix(shared.target_dir)?
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    let search_entry = search::Entry::new(&page.title, url, &contents, &rendered_contents);
+
+    trace!(log, "rendering to html"; "file" => page.path.display(), "rendered file" => page.rendered_path.display());
+    let mut file = File::create(&page.rendered_path)?;
+
+    trace!(log, "writing rendered file"; "file" => page.rendered_path.display());
+    file.write_all(
+        shared
+            .handlebars
+            .render(
+                "page",
+                &json!({
+                    "contents": rendered_contents,
+                    "nest-count": page.nesting_count,
+                    "base-url": shared.base_url,
+                    "menu": shared.menu,
+                    "title": page.title,
+                    "site-title": shared.site_name,
+                    "theme": shared.theme
+                }),
+            )?
+            .as_bytes(),
+    )?;
+
+    Ok(RenderedPage {
+        search_entry,
+        broken_links,
+    })
+}
+
 fn create_menu(
     config: &Config,
     log: &Logger,
@@ -147,29 +385,47 @@ fn create_menu(
 ) -> Result<Vec<serde_json::Value>> {
     debug!(log, "reading Menu.toml");
 
+    let menu_path = config.menu_path();
+
     let mut contents = String::new();
-    let mut toml_file = File::open(config.menu_path())?;
+    let mut toml_file = File::open(&menu_path)?;
     toml_file.read_to_string(&mut contents)?;
-    let doc = contents.parse::<toml_edit::Document>()?;
 
-    let mut json = Vec::new();
+    // api pages don't have ids, and aren't listed in the menu
+    let page_ids: Vec<&str> = files
+        .iter()
+        .filter(|f| !f.id.is_empty())
+        .map(|f| &f.id[..])
+        .collect();
+
+    let checked = menu::check(&contents, &page_ids);
+
+    if !checked.errors.is_empty() {
+        let errors = checked
+            .errors
+            .into_iter()
+            .map(|(line, message)| error::Menu {
+                path: menu_path.clone(),
+                line,
+                message,
+            })
+            .collect();
+
+        return Err(error::MenuErrors { errors }.into());
+    }
 
-    for (title, body) in doc.iter() {
-        let mut body_contents = Vec::new();
+    for id in &checked.unlisted {
+        warn!(log, "page is not in any section of Menu.toml"; "id" => id);
+    }
 
-        let body = body.as_value()
-            .expect("toml is misformatted; body is not a value");
-        let body = body.as_array()
-            .expect("toml is misformatted, body is not an array");
+    let mut json = Vec::new();
 
-        for id in body.iter() {
-            let id = id.as_str()
-                .expect("toml is misformatted, title is not a string");
+    for section in checked.sections {
+        let mut body_contents = Vec::new();
 
-            let file = files
-                .iter()
-                .find(|f| f.id == id)
-                .expect("could not find file with this id");
+        for id in &section.ids {
+            // the check made sure that every id belongs to a file
+            let file = files.iter().find(|f| &f.id == id).unwrap();
 
             body_contents.push(json!({
                     "title": file.title,
@@ -179,8 +435,8 @@ fn create_menu(
         }
 
         json.push(json!({
-                "title": title,
-                "slug": slugify!(title),
+                "title": section.title,
+                "slug": slugify!(&section.title),
                 "contents": body_contents,
             }));
     }
@@ -188,6 +444,68 @@ fn create_menu(
     Ok(json)
 }
 
+/// Find the item that each page of the API docs documents, so that links to items can be resolved.
+fn create_api_index(
+    config: &Config,
+    log: &Logger,
+    files: &[DocMarkdown],
+    targets: &[cargo::Target],
+) -> Result<ApiIndex> {
+    let api_dir = config.api_markdown_path();
+
+    debug!(log, "indexing api docs"; "dir" => api_dir.display());
+
+    let mut index = ApiIndex::default();
+
+    // this is where `api::create` puts each target's docs
+    for target in targets {
+        let path = if targets.len() == 1 {
+            Vec::new()
+        } else {
+            vec![api::target_dir_name(target, targets)]
+        };
+
+        index.add_crate(target.crate_name(), path);
+    }
+
+    for file in files {
+        let path = file.entry.path();
+
+        let relative_path = match path.strip_prefix(&api_dir) {
+            Ok(relative_path) => relative_path,
+            Err(_) => continue,
+        };
+
+        let mut contents = String::new();
+        File::open(path)?.read_to_string(&mut contents)?;
+
+        // this skips pages that aren't about an item, like the overviews
+        if search::api_item(&contents).is_none() {
+            continue;
+        }
+
+        let mut item_path: Vec<String> = relative_path
+            .with_extension("")
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+
+        // a README is the root of a crate, which is named after its directory
+        let url = if item_path.last().map(|name| &name[..]) == Some("README") {
+            item_path.pop();
+            item_path.iter().fold(String::from("api/"), |url, name| url + name + "/") + "index.html"
+        } else {
+            format!("api/{}.html", item_path.join("/"))
+        };
+
+        // with only one target, the crate root is the api directory itself, so its path is empty
+        trace!(log, "indexed item"; "path" => item_path.join("::"), "url" => url.clone());
+        index.insert(item_path, url);
+    }
+
+    Ok(index)
+}
+
 fn collect_files_to_process(config: &Config, log: &Logger) -> Result<Vec<DocMarkdown>> {
     let docs_dir = config.markdown_path();
 
@@ -196,6 +514,9 @@ fn collect_files_to_process(config: &Config, log: &Logger) -> Result<Vec<DocMark
     // render all other *.md files as *.html, walking the tree
     let mut entries = Vec::new();
 
+    // rather than stopping at the first broken file, we report all of them at once
+    let mut errors = Vec::new();
+
     for entry in WalkDir::new(&docs_dir) {
         let entry = entry?;
         let path = entry.path();
@@ -240,24 +561,19 @@ fn collect_files_to_process(config: &Config, log: &Logger) -> Result<Vec<DocMark
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
 
-        if &contents[0..3] != "---" {
-            panic!("for now you need an explicit --- block in your files");
-        }
+        let (id, title) = match read_front_matter(&contents) {
+            Ok(metadata) => metadata,
+            Err((line, message)) => {
+                errors.push(error::FrontMatter {
+                    path: path.to_path_buf(),
+                    line,
+                    message,
+                });
 
-        let metadata = if let Some(index) = contents[3..].find("---") {
-            &contents[3..(index + 3)]
-        } else {
-            panic!("you need a closing --- for the metadata");
+                continue;
+            }
         };
 
-        let doc = metadata.parse::<toml_edit::Document>()?;
-        let id = doc["id"].as_str().expect("id must be a string").to_string();
-
-        let title = doc["title"]
-            .as_str()
-            .expect("title must be a string")
-            .to_string();
-
         // we certainly have a file name, since we're looping over real files
         let file_name = path.file_name().unwrap();
 
@@ -282,5 +598,217 @@ fn collect_files_to_process(config: &Config, log: &Logger) -> Result<Vec<DocMark
         });
     }
 
+    if !errors.is_empty() {
+        return Err(error::FrontMatterErrors { errors }.into());
+    }
+
     Ok(entries)
 }
+
+/// A problem with front matter: the line it's on, starting at one, and what's wrong.
+type FrontMatterProblem = (usize, String);
+
+/// Split the contents of a markdown file into its front matter and the markdown after it.
+///
+/// Front matter is the toml between a `---` on the first line and the next line that's `---`.
+/// Returns `None` if there's no front matter at all.
+fn split_front_matter(
+    contents: &str,
+) -> ::std::result::Result<Option<(&str, &str)>, FrontMatterProblem> {
+    let mut lines = contents.split('\n');
+
+    let metadata_start = match lines.next() {
+        Some(first) if first.trim_right() == "---" => first.len() + 1,
+        _ => return Ok(None),
+    };
+
+    let mut offset = metadata_start;
+
+    for line in lines {
+        if line.trim_right() == "---" {
+            let metadata = &contents[metadata_start..offset];
+
+            // the closing line may be the very end of the file, without a newline
+            let body_start = cmp::min(offset + line.len() + 1, contents.len());
+
+            return Ok(Some((metadata, &contents[body_start..])));
+        }
+
+        offset += line.len() + 1;
+    }
+
+    Err((
+        1,
+        String::from("found an opening `---`, but no closing `---` for the front matter"),
+    ))
+}
+
+/// Read the `id` and `title` out of the front matter of a markdown file.
+fn read_front_matter(
+    contents: &str,
+) -> ::std::result::Result<(String, String), FrontMatterProblem> {
+    let metadata = match split_front_matter(contents)? {
+        Some((metadata, _)) => metadata,
+        None => {
+            return Err((
+                1,
+                String::from("expected front matter, starting with a `---` line"),
+            ))
+        }
+    };
+
+    let doc = metadata
+        .parse::<toml_edit::Document>()
+        .map_err(|e| toml_problem(metadata, &e.to_string()))?;
+
+    let string_value = |key: &str| {
+        // point at the key if it's there, otherwise at the opening `---`
+        let line = metadata
+            .lines()
+            .position(|line| defines(line, key))
+            .map(|index| index + 2)
+            .unwrap_or(1);
+
+        if doc[key].is_none() {
+            return Err((line, format!("front matter is missing `{}`", key)));
+        }
+
+        doc[key]
+            .as_str()
+            .map(|value| value.to_string())
+            .ok_or_else(|| (line, format!("`{}` must be a string", key)))
+    };
+
+    let id = string_value("id")?;
+    let title = string_value("title")?;
+
+    Ok((id, title))
+}
+
+/// Work out where the syntax error in some front matter is, in the whole file.
+///
+/// toml_edit only says where it is in its message, and counts from the start of the front matter,
+/// so the line comes from toml instead, if it agrees that there's an error.
+fn toml_problem(metadata: &str, toml_edit_message: &str) -> FrontMatterProblem {
+    let e = match toml::from_str::<toml::Value>(metadata) {
+        Err(e) => e,
+        // the front matter starts on the second line of the file
+        Ok(_) => return (2, format!("front matter is not valid toml: {}", toml_edit_message)),
+    };
+
+    let message = e.to_string();
+
+    let (line, message) = match e.line_col() {
+        Some((line, column)) => {
+            // the line in the message is within the front matter, so it's replaced
+            let message = match message.rfind(" at line ") {
+                Some(index) => &message[..index],
+                None => &message[..],
+            };
+
+            (line + 2, format!("{} at column {}", message, column + 1))
+        }
+        None => (2, message),
+    };
+
+    (line, format!("front matter is not valid toml: {}", message))
+}
+
+/// Whether a line of toml sets `key`, like `key = "value"`.
+fn defines(line: &str, key: &str) -> bool {
+    let line = line.trim_left();
+
+    line.starts_with(key) && line[key.len()..].trim_left().starts_with('=')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_front_matter, split_front_matter};
+
+    #[test]
+    fn splits_front_matter_from_body() {
+        let contents = "---\nid = \"guide\"\n---\n# Guide\n";
+
+        assert_eq!(
+            split_front_matter(contents),
+            Ok(Some(("id = \"guide\"\n", "# Guide\n")))
+        );
+    }
+
+    #[test]
+    fn no_front_matter() {
+        assert_eq!(split_front_matter("# Guide"), Ok(None));
+        assert_eq!(split_front_matter(""), Ok(None));
+        assert_eq!(split_front_matter("é"), Ok(None));
+    }
+
+    #[test]
+    fn front_matter_without_body() {
+        assert_eq!(split_front_matter("---\n---"), Ok(Some(("", ""))));
+    }
+
+    #[test]
+    fn unclosed_front_matter() {
+        let (line, _) = split_front_matter("---\nid = \"guide\"\n# Guide").unwrap_err();
+
+        assert_eq!(line, 1);
+    }
+
+    #[test]
+    fn reads_id_and_title() {
+        let contents = "---\nid = \"guide\"\ntitle = \"Guide\"\n---\n# Guide\n";
+
+        assert_eq!(
+            read_front_matter(contents),
+            Ok((String::from("guide"), String::from("Guide")))
+        );
+    }
+
+    #[test]
+    fn reports_the_line_of_a_bad_value() {
+        let contents = "---\nid = \"guide\"\ntitle = 5\n---\n# Guide\n";
+
+        let (line, message) = read_front_matter(contents).unwrap_err();
+
+        assert_eq!(line, 3);
+        assert_eq!(message, "`title` must be a string");
+    }
+
+    #[test]
+    fn reports_the_line_of_invalid_toml() {
+        let contents = "---\nid = \"guide\"\n\ntitle = = 5\n---\n# Guide\n";
+
+        let (line, message) = read_front_matter(contents).unwrap_err();
+
+        assert_eq!(line, 4);
+        assert!(message.ends_with("at column 9"), "{}", message);
+        assert!(!message.contains("at line"), "{}", message);
+    }
+
+    #[test]
+    fn does_not_mistake_keys_for_others() {
+        let contents = "---\nidentifier = \"x\"\nid = 5\ntitle = \"Guide\"\n---\n";
+
+        let (line, message) = read_front_matter(contents).unwrap_err();
+
+        assert_eq!(line, 3);
+        assert_eq!(message, "`id` must be a string");
+    }
+
+    #[test]
+    fn reports_missing_keys() {
+        let contents = "---\nid = \"guide\"\n---\n# Guide\n";
+
+        let (line, message) = read_front_matter(contents).unwrap_err();
+
+        assert_eq!(line, 1);
+        assert_eq!(message, "front matter is missing `title`");
+    }
+
+    #[test]
+    fn requires_front_matter() {
+        let (line, _) = read_front_matter("# Guide").unwrap_err();
+
+        assert_eq!(line, 1);
+    }
+}
@@ -0,0 +1,177 @@
+//! Remembering what the last build did, so that the next one only renders pages that changed.
+//!
+//! Every page depends on its own markdown, and also on things that every page shares, like the
+//! menu and the page template. We keep a hash of the shared things, and one of each page's
+//! markdown. If the shared hash changes, every page is rendered again; otherwise only the pages
+//! whose markdown changed are.
+
+use serde_json;
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::prelude::*;
+use std::path::Path;
+
+use ops::search;
+use Result;
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct BuildCache {
+    /// The hash of everything that every page depends on
+    shared: u64,
+
+    /// Each page that was built, by its path relative to the docs directory
+    pages: BTreeMap<String, CachedPage>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedPage {
+    /// The hash of the page's markdown
+    source: u64,
+
+    /// The page's entry in the search index, since we need it even when we don't render the page
+    search_entry: search::Entry,
+}
+
+impl BuildCache {
+    /// Load the cache from the last build, if there is one.
+    ///
+    /// A cache that's missing or unreadable just means that everything gets built, so this
+    /// never fails.
+    pub fn load(path: &Path) -> BuildCache {
+        let mut contents = String::new();
+
+        let read = File::open(path).and_then(|mut file| file.read_to_string(&mut contents));
+        if read.is_err() {
+            return BuildCache::default();
+        }
+
+        serde_json::from_str(&contents).unwrap_or_default()
+    }
+
+    /// Start a new cache, for a build with the given shared hash.
+    pub fn new(shared: u64) -> BuildCache {
+        BuildCache {
+            shared,
+            pages: BTreeMap::new(),
+        }
+    }
+
+    /// Take a page out of the cache, if it can be used for a build with the given shared hash,
+    /// and the page's markdown hasn't changed.
+    pub fn take(&mut self, shared: u64, page: &str, source: u64) -> Option<search::Entry> {
+        if self.shared != shared {
+            return None;
+        }
+
+        match self.pages.remove(page) {
+            Some(ref cached) if cached.source != source => None,
+            Some(cached) => Some(cached.search_entry),
+            None => None,
+        }
+    }
+
+    pub fn insert(&mut self, page: String, source: u64, search_entry: search::Entry) {
+        self.pages.insert(
+            page,
+            CachedPage {
+                source,
+                search_entry,
+            },
+        );
+    }
+
+    /// Returns the search index entry of every page, in a consistent order.
+    pub fn search_entries(&self) -> Vec<&search::Entry> {
+        self.pages.values().map(|page| &page.search_entry).collect()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = File::create(path)?;
+        file.write_all(serde_json::to_string(self)?.as_bytes())?;
+
+        Ok(())
+    }
+}
+
+/// Hash anything that can be hashed.
+///
+/// The hashes are saved between builds, so this can't use the standard library's hasher, whose
+/// algorithm may change from one version of Rust to the next.
+pub fn hash<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = FnvHasher::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A 64-bit FNV-1a hasher, which gives the same hashes everywhere.
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> FnvHasher {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash, BuildCache, FnvHasher};
+    use ops::search::Entry;
+
+    use std::hash::Hasher;
+
+    fn entry() -> Entry {
+        Entry::new("Guide", String::from("guide.html"), "# Guide", "<h1>Guide</h1>")
+    }
+
+    #[test]
+    fn reuses_unchanged_pages() {
+        let mut cache = BuildCache::new(1);
+        cache.insert(String::from("guide.md"), hash("# Guide"), entry());
+
+        assert_eq!(cache.take(1, "guide.md", hash("# Guide")), Some(entry()));
+    }
+
+    #[test]
+    fn rebuilds_changed_pages() {
+        let mut cache = BuildCache::new(1);
+        cache.insert(String::from("guide.md"), hash("# Guide"), entry());
+
+        assert_eq!(cache.take(1, "guide.md", hash("# New guide")), None);
+        assert_eq!(cache.take(1, "other.md", hash("# Guide")), None);
+    }
+
+    #[test]
+    fn rebuilds_everything_when_shared_things_change() {
+        let mut cache = BuildCache::new(1);
+        cache.insert(String::from("guide.md"), hash("# Guide"), entry());
+
+        assert_eq!(cache.take(2, "guide.md", hash("# Guide")), None);
+    }
+
+    #[test]
+    fn fnv_hashes() {
+        let mut hasher = FnvHasher::default();
+        hasher.write(b"a");
+
+        // the test vector from the FNV reference
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+    }
+}
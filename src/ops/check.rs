@@ -0,0 +1,338 @@
+use slog::Logger;
+use walkdir::WalkDir;
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use config::Config;
+use error;
+use Result;
+
+/// What we need to know about each generated page to check links to and from it.
+struct Page {
+    /// Every link on the page
+    links: Vec<String>,
+
+    /// Every fragment that a link to this page can end with
+    anchors: HashSet<String>,
+}
+
+/// Check that every link in the generated docs leads somewhere.
+///
+/// Links to other sites aren't checked; only links to files in the output directory, and the
+/// `#fragment` at the end of them, if there is one.
+pub fn check(config: &Config, log: &Logger) -> Result<()> {
+    let log = log.new(o!("command" => "check"));
+    info!(log, "starting");
+
+    // absolute links, like the ones to API items, start at the root of the output
+    let root = config.output_path();
+
+    let mut target_dir = root.clone();
+    if !config.base_url().is_empty() {
+        target_dir.push(config.base_url());
+    }
+
+    if !target_dir.is_dir() {
+        bail!(
+            "there are no docs in `{}` to check. Try `doxidize build`",
+            target_dir.display()
+        );
+    }
+
+    debug!(log, "reading generated pages"; "dir" => target_dir.display());
+
+    let mut pages = HashMap::new();
+
+    for entry in WalkDir::new(&target_dir) {
+        let entry = entry?;
+        let path = entry.path();
+
+        if !path.is_file() || path.extension() != Some("html".as_ref()) {
+            continue;
+        }
+
+        trace!(log, "reading page"; "file" => path.display());
+
+        let mut html = String::new();
+        File::open(path)?.read_to_string(&mut html)?;
+
+        pages.insert(path.to_path_buf(), read_page(&html));
+    }
+
+    let mut dead_links = Vec::new();
+
+    for (path, page) in &pages {
+        for href in &page.links {
+            let problem = match check_link(&root, path, href, &pages) {
+                Ok(()) => continue,
+                Err(problem) => problem,
+            };
+
+            dead_links.push(error::DeadLink {
+                source: source_file(config, &target_dir, path),
+                href: href.clone(),
+                problem,
+            });
+        }
+    }
+
+    if !dead_links.is_empty() {
+        // the pages came out of a map, so put them in an order that's easy to read
+        dead_links.sort_by(|a, b| a.source.cmp(&b.source));
+
+        return Err(error::DeadLinks { errors: dead_links }.into());
+    }
+
+    info!(log, "done"; "pages" => pages.len());
+    Ok(())
+}
+
+/// Collect the links and anchors in a page.
+fn read_page(html: &str) -> Page {
+    let links = attribute_values(html, "href")
+        .into_iter()
+        .map(|href| href.replace("&amp;", "&"))
+        .collect();
+
+    // only what's really in the page counts; rendered headings don't have ids, so a fragment made
+    // from a heading's text leads nowhere in a browser
+    let anchors = attribute_values(html, "id")
+        .into_iter()
+        .chain(attribute_values(html, "name"))
+        .collect();
+
+    Page { links, anchors }
+}
+
+/// Find every value of an attribute with the given name.
+fn attribute_values(html: &str, name: &str) -> Vec<String> {
+    let pattern = format!(" {}=\"", name);
+
+    let mut values = Vec::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find(&pattern) {
+        rest = &rest[start + pattern.len()..];
+
+        let end = match rest.find('"') {
+            Some(end) => end,
+            None => break,
+        };
+
+        values.push(rest[..end].to_string());
+        rest = &rest[end..];
+    }
+
+    values
+}
+
+/// Check a single link on the page at `page_path`.
+fn check_link(
+    root: &Path,
+    page_path: &Path,
+    href: &str,
+    pages: &HashMap<PathBuf, Page>,
+) -> ::std::result::Result<(), String> {
+    if is_external(href) {
+        return Ok(());
+    }
+
+    // queries don't matter for static files
+    let href = href.split('?').next().unwrap_or_default();
+
+    let (file, fragment) = match href.find('#') {
+        Some(index) => (&href[..index], Some(percent_decode(&href[index + 1..]))),
+        None => (href, None),
+    };
+
+    // a browser asks for `my%20page.html` as `my page.html`
+    let file = percent_decode(file);
+
+    let target = if file.is_empty() {
+        page_path.to_path_buf()
+    } else {
+        let target = resolve(root, page_path, &file)
+            .ok_or_else(|| String::from("it leads outside of the docs"))?;
+
+        if target.is_dir() {
+            target.join("index.html")
+        } else {
+            target
+        }
+    };
+
+    if !target.is_file() {
+        return Err(String::from("there is no such file"));
+    }
+
+    match (fragment, pages.get(&target)) {
+        (Some(ref fragment), Some(page)) if !fragment.is_empty() => {
+            if page.anchors.contains(fragment) {
+                Ok(())
+            } else {
+                Err(format!("there is no `#{}` on that page", fragment))
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Does this link go somewhere other than our own files?
+fn is_external(href: &str) -> bool {
+    if href.starts_with("//") {
+        return true;
+    }
+
+    // a scheme, like `https:` or `mailto:`, comes before any `/`, `?` or `#`
+    match href.find(':') {
+        Some(colon) => !href[..colon].contains(|c: char| c == '/' || c == '?' || c == '#'),
+        None => false,
+    }
+}
+
+/// Work out which file a link leads to. Absolute links start at `root`, and relative ones at the
+/// directory of the page they're on.
+///
+/// Returns `None` if the link leads out of `root`.
+fn resolve(root: &Path, page_path: &Path, link: &str) -> Option<PathBuf> {
+    let mut segments: Vec<String> = if link.starts_with('/') {
+        Vec::new()
+    } else {
+        page_path
+            .parent()?
+            .strip_prefix(root)
+            .ok()?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect()
+    };
+
+    for segment in link.split('/') {
+        match segment {
+            "" | "." => (),
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment.to_string()),
+        }
+    }
+
+    Some(segments.iter().fold(root.to_path_buf(), |path, segment| path.join(segment)))
+}
+
+/// Turn escapes like `%20` back into the characters they stand for. Anything that isn't a valid
+/// escape is left as it is.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'%' {
+            text.get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Find the markdown file that a page was rendered from, so that's what we can point people at.
+///
+/// If there isn't one, the page itself is returned.
+fn source_file(config: &Config, target_dir: &Path, page_path: &Path) -> PathBuf {
+    let relative_path = match page_path.strip_prefix(target_dir) {
+        Ok(relative_path) => relative_path,
+        Err(_) => return page_path.to_path_buf(),
+    };
+
+    let source = if relative_path.file_name() == Some("index.html".as_ref()) {
+        relative_path.with_file_name("README.md")
+    } else {
+        relative_path.with_extension("md")
+    };
+
+    let source = config.markdown_path().join(source);
+
+    if source.is_file() {
+        source
+    } else {
+        page_path.to_path_buf()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{attribute_values, is_external, percent_decode, resolve};
+
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn external_links() {
+        assert!(is_external("https://www.rust-lang.org"));
+        assert!(is_external("mailto:someone@example.com"));
+        assert!(is_external("//cdnjs.cloudflare.com/ajax/libs/highlight.js"));
+        assert!(!is_external("guide.html"));
+        assert!(!is_external("/api/index.html"));
+        assert!(!is_external("guide.html#a:b"));
+    }
+
+    #[test]
+    fn resolves_links() {
+        let root = Path::new("/docs");
+        let page = Path::new("/docs/api/examples/Point.html");
+
+        assert_eq!(
+            resolve(root, page, "Speak.html"),
+            Some(PathBuf::from("/docs/api/examples/Speak.html"))
+        );
+        assert_eq!(
+            resolve(root, page, "../../guide.html"),
+            Some(PathBuf::from("/docs/guide.html"))
+        );
+        assert_eq!(
+            resolve(root, page, "/api/index.html"),
+            Some(PathBuf::from("/docs/api/index.html"))
+        );
+        assert_eq!(
+            resolve(root, page, "./"),
+            Some(PathBuf::from("/docs/api/examples"))
+        );
+        assert_eq!(resolve(root, page, "../../../secret.html"), None);
+    }
+
+    #[test]
+    fn reads_attributes() {
+        let html = r#"<a href="one.html">one</a> <a class="x" href="two.html#b">two</a>"#;
+
+        assert_eq!(
+            attribute_values(html, "href"),
+            vec![String::from("one.html"), String::from("two.html#b")]
+        );
+        assert!(attribute_values(html, "id").is_empty());
+    }
+
+    #[test]
+    fn decodes_escapes() {
+        assert_eq!(percent_decode("my%20page.html"), "my page.html");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%2"), "%zz%2");
+    }
+}
@@ -2,6 +2,8 @@ use slog::Logger;
 
 use remove_dir_all as fs;
 
+use std::fs::remove_file;
+
 use Config;
 use Result;
 
@@ -11,6 +13,13 @@ pub fn clean(config: &Config, log: &Logger) -> Result<()> {
 
     fs::remove_dir_all(config.output_path())?;
 
+    // without the output, the cache would only be describing files that are gone
+    let cache_path = config.build_cache_path();
+    if cache_path.is_file() {
+        debug!(log, "removing build cache"; "file" => cache_path.display());
+        remove_file(cache_path)?;
+    }
+
     info!(log, "done");
     Ok(())
 }
@@ -0,0 +1,361 @@
+//! Reporting which parts of the public API are missing docs.
+//!
+//! The save-analysis data only includes public items, so every item it has counts, down to the
+//! fields, variants and methods of each type. An item is documented if it has a doc comment, and
+//! documented functions, methods and types are also expected to have an `# Examples` section, like
+//! the standard library's.
+
+use serde_json;
+use slog::Logger;
+
+use std::collections::BTreeMap;
+
+use cargo;
+use error;
+use ops::init::api;
+use Config;
+use Result;
+
+/// A public item, and its docs.
+#[derive(Debug)]
+struct Item {
+    location: Location,
+
+    /// The qualname of the module the item is in, or of the crate, for the crate itself. Fields,
+    /// variants and methods are in the module of their type.
+    module: String,
+
+    docs: String,
+}
+
+/// Where to find an item that's missing something.
+#[derive(Debug, PartialEq, Serialize)]
+struct Location {
+    qualname: String,
+    kind: &'static str,
+    file: String,
+    line: u32,
+}
+
+/// How much of a single module is documented.
+#[derive(Debug, PartialEq, Serialize)]
+struct ModuleCoverage {
+    module: String,
+    items: usize,
+    documented: usize,
+    coverage: f64,
+}
+
+/// How much of the whole API is documented, and what's missing.
+#[derive(Debug, Serialize)]
+struct Report {
+    items: usize,
+    documented: usize,
+    coverage: f64,
+    modules: Vec<ModuleCoverage>,
+    undocumented: Vec<Location>,
+    missing_examples: Vec<Location>,
+}
+
+/// How `coverage` should report what it finds.
+#[derive(Debug, Default)]
+pub struct CoverageOptions {
+    /// Print the report as json, rather than text
+    pub json: bool,
+
+    /// The percentage of items that have to be documented, if any
+    pub fail_under: Option<f64>,
+}
+
+pub fn coverage(config: &Config, log: &Logger, options: &CoverageOptions) -> Result<()> {
+    let log = log.new(o!("command" => "coverage"));
+    info!(log, "starting");
+
+    let metadata = cargo::retrieve_metadata(config.manifest_path())?;
+    let targets =
+        cargo::targets_from_metadata(&metadata, config.manifest_path(), config.api_targets())?;
+
+    api::generate_and_load_analysis(config, &targets, &log)?;
+
+    let mut items = Vec::new();
+
+    for target in &targets {
+        debug!(log, "collecting public items"; o!("target" => target.name.clone()));
+        items.extend(api::public_items(config, target)?.into_iter().map(|item| Item {
+            location: Location {
+                qualname: item.qualname,
+                kind: item.kind,
+                file: item.file.display().to_string(),
+                line: item.line,
+            },
+            module: item.module,
+            docs: item.docs,
+        }));
+    }
+
+    let report = summarize(items);
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", render_text(&report));
+    }
+
+    if let Some(threshold) = options.fail_under {
+        if report.coverage < threshold {
+            return Err(error::CoverageTooLow {
+                coverage: report.coverage,
+                threshold,
+            }.into());
+        }
+    }
+
+    info!(log, "done");
+    Ok(())
+}
+
+/// Work out how much is documented, overall and in each module, and list what's missing.
+fn summarize(items: Vec<Item>) -> Report {
+    let mut modules = BTreeMap::new();
+    let mut undocumented = Vec::new();
+    let mut missing_examples = Vec::new();
+
+    for item in items {
+        let documented = !item.docs.trim().is_empty();
+
+        {
+            let counts = modules.entry(item.module).or_insert((0, 0));
+            counts.0 += 1;
+
+            if documented {
+                counts.1 += 1;
+            }
+        }
+
+        if !documented {
+            undocumented.push(item.location);
+        } else if needs_examples(item.location.kind) && !has_examples(&item.docs) {
+            missing_examples.push(item.location);
+        }
+    }
+
+    let modules: Vec<ModuleCoverage> = modules
+        .into_iter()
+        .map(|(module, (items, documented))| ModuleCoverage {
+            module,
+            items,
+            documented,
+            coverage: percentage(documented, items),
+        })
+        .collect();
+
+    let items = modules.iter().map(|module| module.items).sum();
+    let documented = modules.iter().map(|module| module.documented).sum();
+
+    Report {
+        items,
+        documented,
+        coverage: percentage(documented, items),
+        modules,
+        undocumented,
+        missing_examples,
+    }
+}
+
+fn percentage(documented: usize, items: usize) -> f64 {
+    // there's nothing missing from an empty API
+    if items == 0 {
+        100.0
+    } else {
+        documented as f64 * 100.0 / items as f64
+    }
+}
+
+/// Whether items of this kind should show how they're used.
+fn needs_examples(kind: &str) -> bool {
+    match kind {
+        "function" | "method" | "struct" | "enum" | "trait" => true,
+        _ => false,
+    }
+}
+
+/// Whether some docs have an `# Examples` section, at any heading level.
+fn has_examples(docs: &str) -> bool {
+    let mut in_code = false;
+
+    for line in docs.lines() {
+        let line = line.trim();
+
+        // lines starting with # in code blocks are hidden lines, not headings
+        if line.starts_with("```") || line.starts_with("~~~") {
+            in_code = !in_code;
+            continue;
+        }
+
+        if in_code || !line.starts_with('#') {
+            continue;
+        }
+
+        let heading = line.trim_left_matches('#').trim();
+
+        if heading.eq_ignore_ascii_case("examples") || heading.eq_ignore_ascii_case("example") {
+            return true;
+        }
+    }
+
+    false
+}
+
+fn render_text(report: &Report) -> String {
+    let mut text = String::new();
+
+    let list = |text: &mut String, title: &str, locations: &[Location]| {
+        if locations.is_empty() {
+            return;
+        }
+
+        text.push_str(&format!("{}:\n", title));
+
+        for location in locations {
+            text.push_str(&format!(
+                "  {} {} ({}:{})\n",
+                location.kind, location.qualname, location.file, location.line
+            ));
+        }
+
+        text.push('\n');
+    };
+
+    list(&mut text, "Undocumented items", &report.undocumented);
+    list(
+        &mut text,
+        "Documented items without an `# Examples` section",
+        &report.missing_examples,
+    );
+
+    text.push_str("Coverage by module:\n");
+
+    let width = report
+        .modules
+        .iter()
+        .map(|module| module.module.len())
+        .max()
+        .unwrap_or(0);
+
+    for module in &report.modules {
+        text.push_str(&format!(
+            "  {:width$}  {:>4}/{:<4} {:>5.1}%\n",
+            module.module,
+            module.documented,
+            module.items,
+            module.coverage,
+            width = width
+        ));
+    }
+
+    text.push_str(&format!(
+        "\n{} of {} public items are documented ({:.1}%)\n",
+        report.documented, report.items, report.coverage
+    ));
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{has_examples, summarize, Item, Location};
+
+    fn item(qualname: &str, kind: &'static str, module: &str, docs: &str) -> Item {
+        Item {
+            location: Location {
+                qualname: qualname.to_string(),
+                kind,
+                file: String::from("src/lib.rs"),
+                line: 1,
+            },
+            module: module.to_string(),
+            docs: docs.to_string(),
+        }
+    }
+
+    #[test]
+    fn summarizes_by_module() {
+        let report = summarize(vec![
+            item("example", "module", "example", "The crate."),
+            item("example::run", "function", "example", ""),
+            item("example::inner", "module", "example", "Inner things."),
+            item(
+                "example::inner::Thing",
+                "struct",
+                "example::inner",
+                "A thing.\n\n# Examples\n\n```\nThing;\n```",
+            ),
+            item("example::inner::MAX", "const", "example::inner", "The most."),
+        ]);
+
+        assert_eq!(report.items, 5);
+        assert_eq!(report.documented, 4);
+        assert_eq!(format!("{:.1}", report.coverage), "80.0");
+
+        let modules: Vec<_> = report
+            .modules
+            .iter()
+            .map(|module| (&module.module[..], module.documented, module.items))
+            .collect();
+        assert_eq!(modules, vec![("example", 2, 3), ("example::inner", 2, 2)]);
+
+        let undocumented: Vec<_> = report.undocumented.iter().map(|l| &l.qualname[..]).collect();
+        assert_eq!(undocumented, vec!["example::run"]);
+
+        // modules and consts don't need examples
+        assert!(report.missing_examples.is_empty());
+    }
+
+    #[test]
+    fn counts_members_in_the_module_of_their_type() {
+        let report = summarize(vec![
+            item("example::Point", "struct", "example", "A point.\n\n# Examples\n"),
+            item("example::Point::x", "field", "example", ""),
+            item("example::Point::new", "method", "example", "Makes one."),
+            item("example::Light::Red", "variant", "example", "Stop."),
+        ]);
+
+        assert_eq!(report.items, 4);
+        assert_eq!(report.documented, 3);
+        assert_eq!(report.modules.len(), 1);
+
+        let undocumented: Vec<_> = report.undocumented.iter().map(|l| &l.qualname[..]).collect();
+        assert_eq!(undocumented, vec!["example::Point::x"]);
+
+        // methods need examples too, but fields and variants don't
+        let missing: Vec<_> = report
+            .missing_examples
+            .iter()
+            .map(|l| &l.qualname[..])
+            .collect();
+        assert_eq!(missing, vec!["example::Point::new"]);
+    }
+
+    #[test]
+    fn reports_missing_examples() {
+        let report = summarize(vec![item("example::run", "function", "example", "Runs it.")]);
+
+        assert_eq!(format!("{:.1}", report.coverage), "100.0");
+        assert_eq!(report.missing_examples[0].qualname, "example::run");
+    }
+
+    #[test]
+    fn finds_examples_sections() {
+        assert!(has_examples("Runs it.\n\n# Examples\n\n```\nrun();\n```"));
+        assert!(has_examples("Runs it.\n\n## Example\n"));
+        assert!(!has_examples("Runs it."));
+
+        // hidden lines in code blocks aren't headings
+        assert!(!has_examples("Runs it.\n\n```\n# Examples\nrun();\n```"));
+    }
+
+    #[test]
+    fn empty_api_is_covered() {
+        assert_eq!(format!("{:.1}", summarize(Vec::new()).coverage), "100.0");
+    }
+}
@@ -0,0 +1,269 @@
+//! Unified diffs, like `diff -u` makes, for showing how a file would change.
+
+use std::cmp;
+
+/// How many unchanged lines to show around each change.
+const CONTEXT: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Tag {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Make a unified diff of two versions of a file, with `name` in the header.
+pub fn unified(name: &str, old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let lines = diff_lines(&old_lines, &new_lines);
+
+    // where each line is, in the old and new files
+    let mut positions = Vec::with_capacity(lines.len());
+    let (mut old_position, mut new_position) = (0, 0);
+
+    for &(tag, _) in &lines {
+        positions.push((old_position, new_position));
+
+        match tag {
+            Tag::Equal => {
+                old_position += 1;
+                new_position += 1;
+            }
+            Tag::Delete => old_position += 1,
+            Tag::Insert => new_position += 1,
+        }
+    }
+
+    let changes: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|&(_, &(tag, _))| tag != Tag::Equal)
+        .map(|(index, _)| index)
+        .collect();
+
+    let mut diff = format!("--- a/{}\n+++ b/{}\n", name, name);
+
+    let mut i = 0;
+    while i < changes.len() {
+        let start = changes[i].saturating_sub(CONTEXT);
+
+        // changes that are close enough for their context to overlap go in the same hunk
+        while i + 1 < changes.len() && changes[i + 1] - changes[i] <= 2 * CONTEXT + 1 {
+            i += 1;
+        }
+
+        let end = cmp::min(lines.len(), changes[i] + CONTEXT + 1);
+        i += 1;
+
+        let hunk = &lines[start..end];
+        let old_length = hunk.iter().filter(|&&(tag, _)| tag != Tag::Insert).count();
+        let new_length = hunk.iter().filter(|&&(tag, _)| tag != Tag::Delete).count();
+        let (old_start, new_start) = positions[start];
+
+        diff.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(old_start, old_length),
+            range(new_start, new_length)
+        ));
+
+        for &(tag, line) in hunk {
+            let prefix = match tag {
+                Tag::Equal => ' ',
+                Tag::Delete => '-',
+                Tag::Insert => '+',
+            };
+
+            diff.push(prefix);
+            diff.push_str(line);
+            diff.push('\n');
+        }
+    }
+
+    diff
+}
+
+/// Describe a range of lines in a hunk header, given how many lines come before it.
+fn range(before: usize, length: usize) -> String {
+    // lines are counted from one, but an empty range is named by the line before it
+    let start = if length == 0 { before } else { before + 1 };
+
+    if length == 1 {
+        start.to_string()
+    } else {
+        format!("{},{}", start, length)
+    }
+}
+
+/// Work out which lines were kept, deleted and inserted, with as few changes as possible.
+///
+/// This is Myers' algorithm, in the version that needs space linear in the length of the files,
+/// since generated pages can get long.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Tag, &'a str)> {
+    let mut lines = Vec::with_capacity(cmp::max(old.len(), new.len()));
+    diff_range(old, new, &mut lines);
+    lines
+}
+
+/// Diff part of the files onto the end of `lines`, by splitting it in two where the middle of
+/// its shortest edit script is, until the parts are trivial.
+fn diff_range<'a>(old: &[&'a str], new: &[&'a str], lines: &mut Vec<(Tag, &'a str)>) {
+    // most of a regenerated page is usually the same, so this leaves little to search
+    let prefix = old.iter().zip(new).take_while(|&(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|&(a, b)| a == b)
+        .count();
+
+    lines.extend(old[..prefix].iter().map(|&line| (Tag::Equal, line)));
+
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    if old_middle.is_empty() {
+        lines.extend(new_middle.iter().map(|&line| (Tag::Insert, line)));
+    } else if new_middle.is_empty() {
+        lines.extend(old_middle.iter().map(|&line| (Tag::Delete, line)));
+    } else {
+        let (x, y) = middle_snake(old_middle, new_middle);
+
+        diff_range(&old_middle[..x], &new_middle[..y], lines);
+        diff_range(&old_middle[x..], &new_middle[y..], lines);
+    }
+
+    lines.extend(old[old.len() - suffix..].iter().map(|&line| (Tag::Equal, line)));
+}
+
+/// Find a point halfway along a shortest edit script from `old` to `new`, by searching from both
+/// ends at once until the searches meet. Neither may be empty.
+///
+/// Points are `(x, y)`, where `x` lines of `old` and `y` lines of `new` come before it. A
+/// diagonal `k` is every point where `x - y == k`. `forward[k]` is the furthest `x` that the
+/// search from the start has reached on diagonal `k`, and `backward[k]` the same for the search
+/// from the end, counting from the ends of the files instead.
+fn middle_snake(old: &[&str], new: &[&str]) -> (usize, usize) {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+
+    let max = (n + m + 1) / 2;
+    let offset = max + 1;
+    let mut forward = vec![0; (2 * offset + 1) as usize];
+    let mut backward = vec![0; (2 * offset + 1) as usize];
+
+    let index = |k: isize| (k + offset) as usize;
+
+    for d in 0..max + 1 {
+        let mut k = -d;
+        while k <= d {
+            let mut x = if k == -d || (k != d && forward[index(k - 1)] < forward[index(k + 1)]) {
+                forward[index(k + 1)]
+            } else {
+                forward[index(k - 1)] + 1
+            };
+            let (start_x, start_y) = (x, x - k);
+
+            while x < n && x - k < m && old[x as usize] == new[(x - k) as usize] {
+                x += 1;
+            }
+
+            forward[index(k)] = x;
+
+            // the backward search is one step behind, so the paths can only meet on this side
+            // when the difference in length is odd
+            if odd && (k - delta).abs() < d && x + backward[index(delta - k)] >= n {
+                return (start_x as usize, start_y as usize);
+            }
+
+            k += 2;
+        }
+
+        let mut k = -d;
+        while k <= d {
+            let mut x = if k == -d || (k != d && backward[index(k - 1)] < backward[index(k + 1)]) {
+                backward[index(k + 1)]
+            } else {
+                backward[index(k - 1)] + 1
+            };
+
+            while x < n
+                && x - k < m
+                && old[(n - x - 1) as usize] == new[(m - (x - k) - 1) as usize]
+            {
+                x += 1;
+            }
+
+            backward[index(k)] = x;
+
+            if !odd && (k - delta).abs() <= d && x + forward[index(delta - k)] >= n {
+                return ((n - x) as usize, (m - (x - k)) as usize);
+            }
+
+            k += 2;
+        }
+    }
+
+    unreachable!("the searches always meet by the time they've each made half of the changes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_lines, unified, Tag};
+
+    #[test]
+    fn shows_changes_with_context() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n9\n10\n";
+
+        assert_eq!(
+            unified("a.md", old, new),
+            "--- a/a.md\n+++ b/a.md\n@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n"
+        );
+    }
+
+    #[test]
+    fn separates_distant_changes() {
+        let old = "a\n1\n2\n3\n4\n5\n6\n7\n8\nb\n";
+        let new = "A\n1\n2\n3\n4\n5\n6\n7\n8\nB\n";
+
+        let diff = unified("a.md", old, new);
+
+        assert!(diff.contains("@@ -1,4 +1,4 @@\n-a\n+A\n 1\n 2\n 3\n"), "{}", diff);
+        assert!(diff.contains("@@ -7,4 +7,4 @@\n 6\n 7\n 8\n-b\n+B\n"), "{}", diff);
+    }
+
+    #[test]
+    fn new_files() {
+        assert_eq!(
+            unified("a.md", "", "one\ntwo\n"),
+            "--- a/a.md\n+++ b/a.md\n@@ -0,0 +1,2 @@\n+one\n+two\n"
+        );
+    }
+
+    #[test]
+    fn finds_the_fewest_changes() {
+        let old: Vec<&str> = "a b c a b b a".split(' ').collect();
+        let new: Vec<&str> = "c b a b a c".split(' ').collect();
+
+        let lines = diff_lines(&old, &new);
+
+        let changes = lines.iter().filter(|&&(tag, _)| tag != Tag::Equal).count();
+        assert_eq!(changes, 5);
+
+        let kept: Vec<_> = lines
+            .iter()
+            .filter(|&&(tag, _)| tag != Tag::Insert)
+            .map(|&(_, line)| line)
+            .collect();
+        assert_eq!(kept, old);
+
+        let made: Vec<_> = lines
+            .iter()
+            .filter(|&&(tag, _)| tag != Tag::Delete)
+            .map(|&(_, line)| line)
+            .collect();
+        assert_eq!(made, new);
+    }
+}
@@ -0,0 +1,809 @@
+//! Markdown that's generated from the source, but that people can add to.
+//!
+//! Everything Doxidize generates is wrapped in pairs of markers, one around the top of each page
+//! and one around each item on it, like a method or a variant. Each start marker records the item
+//! and a checksum of what was generated for it. When the markdown is generated again, each item
+//! only replaces what's between its own markers, so anything written above, below or in between
+//! them is kept. If what's between a pair of markers doesn't match the checksum any more, someone
+//! has edited it by hand, so that section is left alone and reported instead.
+//!
+//! The templates mark where each item starts with an `ITEM` comment, which is replaced by the
+//! item's start marker when the markdown is written.
+
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use error;
+use ops::cache::FnvHasher;
+use Result;
+
+const START: &str = "<!-- doxidize:generated ";
+const END: &str = "<!-- doxidize:end-generated -->";
+const ITEM: &str = "<!-- doxidize:item ";
+
+/// The files and directories that generating docs produced.
+#[derive(Debug, Default)]
+pub struct GeneratedFiles {
+    /// Every file and directory that was generated, so that `update` can clean up the rest
+    paths: HashSet<PathBuf>,
+
+    /// The sections that couldn't be updated, because they were edited by hand
+    conflicts: Vec<error::GeneratedConflict>,
+
+    /// Whether to only work out what would change, rather than changing anything
+    dry_run: bool,
+
+    /// Whether to replace files that have no generated section, rather than reporting them
+    adopt: bool,
+
+    /// What a dry run would have changed
+    changes: Vec<Change>,
+}
+
+/// A change to a file that a dry run would have made.
+#[derive(Debug)]
+pub enum Change {
+    Create {
+        path: PathBuf,
+        contents: String,
+    },
+    Modify {
+        path: PathBuf,
+        old: String,
+        new: String,
+    },
+}
+
+impl GeneratedFiles {
+    pub fn new() -> GeneratedFiles {
+        GeneratedFiles::default()
+    }
+
+    /// Generate files without touching the filesystem, just keeping track of what would change.
+    pub fn dry_run() -> GeneratedFiles {
+        GeneratedFiles {
+            dry_run: true,
+            ..GeneratedFiles::default()
+        }
+    }
+
+    /// Replace the whole of any file that has no generated section, rather than reporting it as
+    /// a conflict.
+    ///
+    /// Files that are just what would be generated anyway are always replaced, since they were
+    /// generated before there were markers.
+    pub fn set_adopt(&mut self, adopt: bool) {
+        self.adopt = adopt;
+    }
+
+    pub fn create_dir(&mut self, path: &Path) -> Result<()> {
+        if !self.dry_run {
+            fs::create_dir_all(path)?;
+        }
+
+        self.paths.insert(path.to_path_buf());
+
+        Ok(())
+    }
+
+    /// Write generated markdown to a file, keeping anything outside the generated sections of
+    /// the file that's already there.
+    ///
+    /// Front matter has to be at the very top of the file, so any that was generated goes above
+    /// the markers. Once the file exists, its front matter is left as it is.
+    pub fn write(&mut self, path: &Path, generated: &str) -> Result<()> {
+        // conflicting files are still ours, so they mustn't be cleaned up
+        self.paths.insert(path.to_path_buf());
+
+        let existing = read(path)?;
+
+        let (front_matter, generated) = generated.split_at(front_matter_length(generated));
+
+        let contents = match existing {
+            Some(ref existing) => {
+                let merged = match adopt(existing, front_matter, generated, self.adopt) {
+                    Some(adopted) => Ok((adopted, Vec::new())),
+                    None => merge(existing, generated),
+                };
+
+                // a hand edit only stops its own section from being updated
+                let (contents, problems) = match merged {
+                    Ok(merged) => merged,
+                    Err(problem) => (existing.clone(), vec![problem]),
+                };
+
+                for problem in problems {
+                    self.conflicts.push(error::GeneratedConflict {
+                        path: path.to_path_buf(),
+                        problem,
+                    });
+                }
+
+                contents
+            }
+            None => format!("{}{}", front_matter, wrap_all(&items(generated))),
+        };
+
+        self.save(path, existing, contents)
+    }
+
+    /// Replace the whole of a file that isn't generated markdown, like `Menu.toml`.
+    pub fn replace(&mut self, path: &Path, contents: String) -> Result<()> {
+        self.paths.insert(path.to_path_buf());
+
+        let existing = read(path)?;
+
+        self.save(path, existing, contents)
+    }
+
+    fn save(&mut self, path: &Path, existing: Option<String>, contents: String) -> Result<()> {
+        // leaving unchanged files alone means that the build doesn't render them again
+        if existing.as_ref() == Some(&contents) {
+            return Ok(());
+        }
+
+        if !self.dry_run {
+            File::create(path)?.write_all(contents.as_bytes())?;
+            return Ok(());
+        }
+
+        let path = path.to_path_buf();
+
+        self.changes.push(match existing {
+            Some(old) => Change::Modify {
+                path,
+                old,
+                new: contents,
+            },
+            None => Change::Create { path, contents },
+        });
+
+        Ok(())
+    }
+
+    pub fn paths(&self) -> &HashSet<PathBuf> {
+        &self.paths
+    }
+
+    /// Returns what a dry run would have changed, in the order it was generated.
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// Fail if any generated sections couldn't be updated.
+    pub fn check_conflicts(self) -> Result<()> {
+        if self.conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(error::GeneratedConflicts {
+                errors: self.conflicts,
+            }.into())
+        }
+    }
+}
+
+/// Read a file, if it exists.
+fn read(path: &Path) -> Result<Option<String>> {
+    let mut contents = String::new();
+    let read = File::open(path).and_then(|mut file| file.read_to_string(&mut contents));
+
+    match read {
+        Ok(_) => Ok(Some(contents)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The length of the front matter at the start of some generated markdown, including both `---`
+/// lines, or zero if there isn't any.
+fn front_matter_length(generated: &str) -> usize {
+    let mut lines = generated.split('\n');
+
+    let mut offset = match lines.next() {
+        Some(first) if first.trim_right() == "---" => first.len() + 1,
+        _ => return 0,
+    };
+
+    for line in lines {
+        offset += line.len() + 1;
+
+        if line.trim_right() == "---" {
+            return cmp::min(offset, generated.len());
+        }
+    }
+
+    // we generated it, so this shouldn't happen, but it's safest to leave it all in the markers
+    0
+}
+
+/// What was generated for one item on a page.
+#[derive(Debug, PartialEq)]
+struct Item<'a> {
+    /// What the item is, like `method new`, or nothing for the top of the page
+    key: &'a str,
+    markdown: &'a str,
+}
+
+/// Split generated markdown into its items, wherever the templates started one.
+fn items(generated: &str) -> Vec<Item> {
+    let mut items = Vec::new();
+
+    let mut key = "";
+    let mut start = 0;
+    let mut offset = 0;
+
+    for line in generated.split('\n') {
+        let next = cmp::min(offset + line.len() + 1, generated.len());
+
+        if line.starts_with(ITEM) {
+            items.push(Item {
+                key,
+                markdown: trim(&generated[start..offset]),
+            });
+
+            key = line[ITEM.len()..].trim().trim_right_matches("-->").trim();
+            start = next;
+        }
+
+        offset = next;
+    }
+
+    items.push(Item {
+        key,
+        markdown: trim(&generated[start..]),
+    });
+
+    // a page that starts with an item has nothing at the top
+    items.retain(|item| !item.key.is_empty() || !item.markdown.is_empty());
+
+    items
+}
+
+/// Trim the blank lines that separate items in the templates.
+fn trim(markdown: &str) -> &str {
+    markdown.trim_left_matches('\n').trim_right()
+}
+
+/// Generated markdown as it was before there were markers.
+fn unmarked(generated: &str) -> String {
+    let lines: Vec<_> = generated
+        .split('\n')
+        .filter(|line| !line.starts_with(ITEM))
+        .collect();
+
+    lines.join("\n")
+}
+
+/// Wrap an item's markdown in the markers.
+fn wrap(item: &Item) -> String {
+    let checksum = checksum(item.markdown);
+
+    let start = if item.key.is_empty() {
+        format!("{}{} -->", START, checksum)
+    } else {
+        format!("{}{} {} -->", START, checksum, item.key)
+    };
+
+    format!("{}\n{}\n{}\n", start, item.markdown, END)
+}
+
+/// Wrap every item on a page in its own markers, with a blank line between them.
+fn wrap_all(items: &[Item]) -> String {
+    let wrapped: Vec<_> = items.iter().map(wrap).collect();
+
+    wrapped.join("\n")
+}
+
+/// Take over a file that has no generated sections, by wrapping newly generated markdown in the
+/// markers and replacing everything but its front matter.
+///
+/// Unless `force` is set, this only happens when the file is just what we'd generate now, since
+/// that means it was generated before there were markers, and nothing has been written in it
+/// since.
+fn adopt(existing: &str, front_matter: &str, generated: &str, force: bool) -> Option<String> {
+    if existing.contains(START) {
+        return None;
+    }
+
+    let unmarked = format!("{}{}", front_matter, unmarked(generated));
+
+    if !force && existing.replace("\r\n", "\n").trim_right() != unmarked.trim_right() {
+        return None;
+    }
+
+    // like with files that have markers, the front matter is theirs once it's been written
+    let front_matter = &existing[..front_matter_length(existing)];
+
+    Some(format!("{}{}", front_matter, wrap_all(&items(generated))))
+}
+
+/// A part of a file that's already been generated.
+#[derive(Debug)]
+enum Part<'a> {
+    /// Anything outside the markers
+    Prose(&'a str),
+    Section(Section<'a>),
+}
+
+/// What was between a pair of markers.
+#[derive(Debug)]
+struct Section<'a> {
+    /// The item it was generated for
+    key: &'a str,
+    /// The checksum in its start marker
+    recorded: &'a str,
+    markdown: &'a str,
+    /// The whole section, including its markers
+    text: &'a str,
+}
+
+impl<'a> Section<'a> {
+    fn is_edited(&self) -> bool {
+        checksum(self.markdown) != self.recorded
+    }
+
+    fn describe(&self) -> String {
+        if self.key.is_empty() {
+            String::from("the generated section at the top")
+        } else {
+            format!("the generated section for `{}`", self.key)
+        }
+    }
+}
+
+/// Split a file into its generated sections and the prose around them.
+///
+/// Returns why it can't be split, if it can't.
+fn parts(existing: &str) -> ::std::result::Result<Vec<Part>, String> {
+    let mut parts = Vec::new();
+    let mut offset = 0;
+
+    while let Some(index) = existing[offset..].find(START) {
+        let start = offset + index;
+
+        let close = existing[start..]
+            .find("-->")
+            .map(|index| start + index)
+            .ok_or_else(|| String::from("the start of a generated section is damaged"))?;
+
+        let marker = existing[start + START.len()..close].trim();
+        let (recorded, key) = match marker.find(' ') {
+            Some(index) => (&marker[..index], marker[index..].trim()),
+            None => (marker, ""),
+        };
+
+        // the section starts on the line after the marker
+        let section_start = close + "-->".len();
+        let section_start = section_start + line_ending(&existing[section_start..]);
+
+        let end = existing[section_start..]
+            .find(END)
+            .map(|index| section_start + index)
+            .ok_or_else(|| String::from("the end of a generated section is missing"))?;
+
+        let markdown = existing[section_start..end].trim_right();
+
+        if markdown.contains(START) {
+            return Err(String::from("the end of a generated section is missing"));
+        }
+
+        let after = end + END.len();
+        let after = after + line_ending(&existing[after..]);
+
+        if start > offset {
+            parts.push(Part::Prose(&existing[offset..start]));
+        }
+
+        parts.push(Part::Section(Section {
+            key,
+            recorded,
+            markdown,
+            text: &existing[start..after],
+        }));
+
+        offset = after;
+    }
+
+    if offset < existing.len() {
+        parts.push(Part::Prose(&existing[offset..]));
+    }
+
+    Ok(parts)
+}
+
+/// Replace each generated section of `existing` with the newly generated markdown for its item.
+///
+/// Sections that have been edited by hand are left alone, and new items go next to the items
+/// they come after. Returns the merged file, along with why any sections couldn't be replaced, or
+/// why nothing could be, if the file can't be merged at all.
+fn merge(
+    existing: &str,
+    generated: &str,
+) -> ::std::result::Result<(String, Vec<String>), String> {
+    let items = items(generated);
+    let parts = parts(existing)?;
+
+    let last = parts
+        .iter()
+        .rposition(|part| match *part {
+            Part::Section(_) => true,
+            Part::Prose(_) => false,
+        })
+        .ok_or_else(|| {
+            String::from(
+                "it has no generated section; run `doxidize update --adopt` to replace it, or \
+                 delete it, to generate it again",
+            )
+        })?;
+
+    // the same item can be on a page more than once, like a method with the same name in two
+    // impl blocks, so the first one is matched with the first section, and so on
+    let mut by_key = HashMap::new();
+    let mut seen = HashMap::new();
+
+    for (index, item) in items.iter().enumerate() {
+        let count = seen.entry(item.key).or_insert(0);
+        by_key.insert((item.key, *count), index);
+        *count += 1;
+    }
+
+    let mut seen = HashMap::new();
+    let matches: Vec<_> = parts
+        .iter()
+        .map(|part| match *part {
+            Part::Section(ref section) => {
+                let count = seen.entry(section.key).or_insert(0);
+                let index = by_key.get(&(section.key, *count)).cloned();
+                *count += 1;
+                index
+            }
+            Part::Prose(_) => None,
+        })
+        .collect();
+
+    let mut placed = vec![false; items.len()];
+    for index in matches.iter().filter_map(|index| *index) {
+        placed[index] = true;
+    }
+
+    let mut merged = String::new();
+    let mut problems = Vec::new();
+    let mut removed = false;
+
+    for (index, part) in parts.iter().enumerate() {
+        let section = match *part {
+            Part::Section(ref section) => section,
+            Part::Prose(text) => {
+                // the blank line that separated a removed section from what's after it goes too
+                let text = if removed && ends_with_blank_line(&merged) {
+                    &text[line_ending(text)..]
+                } else {
+                    text
+                };
+
+                merged.push_str(text);
+
+                removed = false;
+                continue;
+            }
+        };
+
+        removed = false;
+
+        match matches[index] {
+            Some(item) => {
+                // new items go in front of the first item that comes after them
+                for (new, placed) in items.iter().zip(&mut placed).take(item) {
+                    if !*placed {
+                        merged.push_str(&wrap(new));
+                        merged.push('\n');
+                        *placed = true;
+                    }
+                }
+
+                if section.is_edited() {
+                    problems.push(format!(
+                        "{} has been edited by hand; move your changes outside of its \
+                         doxidize:generated markers, or delete the section, to generate it again",
+                        section.describe()
+                    ));
+                    merged.push_str(section.text);
+                } else {
+                    merged.push_str(&wrap(&items[item]));
+                }
+            }
+            None if section.is_edited() => {
+                problems.push(format!(
+                    "{} has been edited by hand, but its item is gone; move your changes \
+                     outside of its doxidize:generated markers, or delete the section",
+                    section.describe()
+                ));
+                merged.push_str(section.text);
+            }
+            None => removed = true,
+        }
+
+        // and anything that comes after every existing item goes after the last section
+        if index == last {
+            for (new, placed) in items.iter().zip(&mut placed) {
+                if !*placed {
+                    separate(&mut merged);
+                    merged.push_str(&wrap(new));
+                    *placed = true;
+                }
+            }
+        }
+    }
+
+    Ok((merged, problems))
+}
+
+/// Whether `merged` is empty or ends with a blank line, so that a section can go after it.
+fn ends_with_blank_line(merged: &str) -> bool {
+    merged.is_empty() || merged.ends_with("\n\n") || merged.ends_with("\n\r\n")
+}
+
+/// End `merged` with a blank line, so that a section can go after it.
+fn separate(merged: &mut String) {
+    if ends_with_blank_line(merged) {
+        return;
+    }
+
+    if !merged.ends_with('\n') {
+        merged.push('\n');
+    }
+
+    merged.push('\n');
+}
+
+/// The length of the line ending at the start of `text`, if there is one.
+fn line_ending(text: &str) -> usize {
+    if text.starts_with("\r\n") {
+        2
+    } else if text.starts_with('\n') {
+        1
+    } else {
+        0
+    }
+}
+
+/// A checksum of some generated markdown that stays the same from one version of Rust to the
+/// next, since it's committed along with the docs.
+fn checksum(markdown: &str) -> String {
+    // line endings may have been changed by git
+    let markdown = markdown.replace("\r\n", "\n");
+
+    let mut hasher = FnvHasher::default();
+    hasher.write(markdown.as_bytes());
+
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adopt, front_matter_length, items, merge, wrap, wrap_all, Item};
+
+    fn top(markdown: &str) -> String {
+        wrap(&Item { key: "", markdown })
+    }
+
+    fn item(key: &str, markdown: &str) -> String {
+        wrap(&Item { key, markdown })
+    }
+
+    fn merged(existing: &str, generated: &str) -> String {
+        let (merged, problems) = merge(existing, generated).unwrap();
+        assert!(problems.is_empty(), "{:?}", problems);
+        merged
+    }
+
+    #[test]
+    fn splits_items() {
+        let generated = "# struct `Point`\n\nDocs\n\n<!-- doxidize:item method new -->\n\n\
+                         ### method `new`\n<!-- doxidize:item method len -->\n### method `len`\n";
+
+        assert_eq!(
+            items(generated),
+            vec![
+                Item {
+                    key: "",
+                    markdown: "# struct `Point`\n\nDocs",
+                },
+                Item {
+                    key: "method new",
+                    markdown: "### method `new`",
+                },
+                Item {
+                    key: "method len",
+                    markdown: "### method `len`",
+                },
+            ]
+        );
+
+        assert_eq!(
+            wrap_all(&items(generated)),
+            format!(
+                "{}\n{}\n{}",
+                top("# struct `Point`\n\nDocs"),
+                item("method new", "### method `new`"),
+                item("method len", "### method `len`")
+            )
+        );
+    }
+
+    #[test]
+    fn keeps_prose_outside_the_markers() {
+        let existing = format!("Intro\n\n{}\nMore prose\n", top("# struct `Point`\n\nOld docs"));
+
+        assert_eq!(
+            merged(&existing, "# struct `Point`\n\nNew docs"),
+            format!("Intro\n\n{}\nMore prose\n", top("# struct `Point`\n\nNew docs"))
+        );
+    }
+
+    #[test]
+    fn keeps_prose_between_items() {
+        let existing = format!(
+            "{}\nAbout new.\n\n{}",
+            top("# struct `Point`"),
+            item("method new", "Old new")
+        );
+
+        assert_eq!(
+            merged(
+                &existing,
+                "# struct `Point`\n<!-- doxidize:item method new -->\nNew new"
+            ),
+            format!(
+                "{}\nAbout new.\n\n{}",
+                top("# struct `Point`"),
+                item("method new", "New new")
+            )
+        );
+    }
+
+    #[test]
+    fn adds_and_removes_items() {
+        let existing = format!(
+            "{}\n{}\nAbout b.\n\n{}\n{}",
+            top("Top"),
+            item("method a", "a"),
+            item("method b", "b"),
+            item("method d", "d")
+        );
+
+        let generated = "Top\n<!-- doxidize:item method b -->\nb\n\
+                         <!-- doxidize:item method c -->\nc\n\
+                         <!-- doxidize:item method d -->\nd\n\
+                         <!-- doxidize:item method e -->\ne\n";
+
+        assert_eq!(
+            merged(&existing, generated),
+            format!(
+                "{}\nAbout b.\n\n{}\n{}\n{}\n{}",
+                top("Top"),
+                item("method b", "b"),
+                item("method c", "c"),
+                item("method d", "d"),
+                item("method e", "e")
+            )
+        );
+    }
+
+    #[test]
+    fn matches_repeated_items_in_order() {
+        let existing = format!("{}\n{}", item("method new", "first"), item("method new", "second"));
+
+        let generated = "<!-- doxidize:item method new -->\nfirst, again\n\
+                         <!-- doxidize:item method new -->\nsecond, again\n";
+
+        assert_eq!(merged(&existing, generated), wrap_all(&items(generated)));
+    }
+
+    #[test]
+    fn moves_old_files_to_items() {
+        // files from before there were items have a single section, which becomes the top
+        let existing = top("# struct `Point`\n\n### method `new`");
+
+        let generated = "# struct `Point`\n<!-- doxidize:item method new -->\n### method `new`";
+
+        assert_eq!(merged(&existing, generated), wrap_all(&items(generated)));
+    }
+
+    #[test]
+    fn replaces_an_unchanged_file() {
+        assert_eq!(merged(&top("Old docs"), "New docs"), top("New docs"));
+    }
+
+    #[test]
+    fn reports_hand_edited_sections() {
+        let edited = item("method new", "Old new").replace("Old new", "Edited new");
+        let existing = format!("{}\n{}", top("Old docs"), edited);
+
+        let (merged, problems) =
+            merge(&existing, "New docs\n<!-- doxidize:item method new -->\nNew new").unwrap();
+
+        // only the edited section is left alone
+        assert_eq!(merged, format!("{}\n{}", top("New docs"), edited));
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("`method new`"), "{}", problems[0]);
+        assert!(problems[0].contains("edited by hand"), "{}", problems[0]);
+    }
+
+    #[test]
+    fn keeps_hand_edited_sections_of_removed_items() {
+        let edited = item("method old", "Old").replace("Old", "Edited");
+        let existing = format!("{}\n{}", top("Docs"), edited);
+
+        let (merged, problems) = merge(&existing, "Docs").unwrap();
+
+        assert_eq!(merged, existing);
+        assert_eq!(problems.len(), 1);
+    }
+
+    #[test]
+    fn reports_files_without_markers() {
+        assert!(merge("# struct `Point`\n", "New docs").is_err());
+
+        let unclosed = top("Old docs").replace("<!-- doxidize:end-generated -->", "");
+        assert!(merge(&unclosed, "New docs").is_err());
+
+        let nested = format!("{}{}", unclosed, top("More"));
+        assert!(merge(&nested, "New docs").is_err());
+    }
+
+    #[test]
+    fn adopts_files_from_before_the_markers() {
+        let front_matter = "---\nid = \"a.rs\"\n---\n";
+        let existing = format!("{}# a.rs\n", front_matter);
+
+        assert_eq!(
+            adopt(&existing, front_matter, "# a.rs", false),
+            Some(format!("{}{}", front_matter, top("# a.rs")))
+        );
+
+        // anything else may have been written by hand
+        assert_eq!(adopt(&existing, front_matter, "# b.rs", false), None);
+        assert_eq!(
+            adopt(&existing, front_matter, "# b.rs", true),
+            Some(format!("{}{}", front_matter, top("# b.rs")))
+        );
+
+        // files with markers are merged instead
+        assert_eq!(adopt(&top("# a.rs"), "", "# a.rs", true), None);
+    }
+
+    #[test]
+    fn adopts_files_with_items() {
+        let existing = "# struct `Point`\n\n### method `new`\n";
+        let generated = "# struct `Point`\n<!-- doxidize:item method new -->\n\n### method `new`\n";
+
+        assert_eq!(
+            adopt(existing, "", generated, false),
+            Some(wrap_all(&items(generated)))
+        );
+    }
+
+    #[test]
+    fn ignores_line_endings() {
+        let existing = format!("{}\n{}", top("one\ntwo"), item("method new", "three"))
+            .replace('\n', "\r\n");
+
+        let (_, problems) = merge(&existing, "New docs").unwrap();
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+
+    #[test]
+    fn finds_front_matter() {
+        let page = "---\nid = \"a.rs\"\n---\n# a.rs\n";
+
+        assert_eq!(&page[..front_matter_length(page)], "---\nid = \"a.rs\"\n---\n");
+        assert_eq!(front_matter_length("# a.rs\n---\n"), 0);
+        assert_eq!(front_matter_length("---\nid = \"a.rs\"\n"), 0);
+    }
+}
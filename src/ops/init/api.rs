@@ -1,18 +1,41 @@
 use analysis::{self, DefKind};
+use analysis_data;
+use serde_json;
 use slog::Logger;
 
-use std::collections::{HashSet, VecDeque};
-use std::fs::{self, File};
-use std::io::prelude::*;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
 
 use error;
-use cargo::{self, Target};
+use cargo::{self, Target, TargetKind};
+use ops::generated::GeneratedFiles;
+use save_analysis::{self, SaveAnalysis};
 use Config;
 use Result;
 use strip_leading_space;
 
-pub fn create(config: &Config, log: &Logger) -> Result<HashSet<PathBuf>> {
+/// Where the API docs for a single target go.
+struct ApiTree {
+    /// The directory the markdown is written to.
+    markdown_path: PathBuf,
+
+    /// The url that directory ends up at once built, like `/doxidize/api`.
+    url: String,
+}
+
+/// The entries for the overview pages, for a single target.
+///
+/// When documenting more than one target, these are combined into one page per kind.
+struct Overviews {
+    modules: String,
+    structs: String,
+    traits: String,
+}
+
+/// Generate the API docs, writing them with `files`, which also keeps track of the files and
+/// folders we created so that `update` can clean up what was left.
+pub fn create(config: &Config, log: &Logger, files: &mut GeneratedFiles) -> Result<()> {
     // ensure that the api dir exists
     let api_dir = config.api_markdown_path();
     debug!(log, "creating api dir";
@@ -20,51 +43,203 @@ pub fn create(config: &Config, log: &Logger) -> Result<HashSet<PathBuf>> {
     fs::create_dir_all(&api_dir)?;
 
     let metadata = cargo::retrieve_metadata(config.manifest_path())?;
-    let target = cargo::target_from_metadata(log, &metadata)?;
+    let targets =
+        cargo::targets_from_metadata(&metadata, config.manifest_path(), config.api_targets())?;
+
+    generate_and_load_analysis(config, &targets, log)?;
+
+    let base_url = if config.base_url().is_empty() {
+        String::new()
+    } else {
+        format!("/{}", config.base_url())
+    };
+
+    // the host doesn't know about impl blocks, so we need the raw data too. we load every crate
+    // up front so that items in one can link to items in another.
+    let crates = targets
+        .iter()
+        .map(|target| SaveAnalysis::load(config, target))
+        .collect::<Result<Vec<_>>>()?;
+
+    // with only one target, its docs go right in the api dir, like they always have. otherwise,
+    // each target gets a directory of its own, and the api README links to each of them. this is
+    // also how every member of a workspace gets its own section.
+    let trees: Vec<ApiTree> = if targets.len() == 1 {
+        vec![ApiTree {
+            markdown_path: api_dir.clone(),
+            url: format!("{}/api", base_url),
+        }]
+    } else {
+        targets
+            .iter()
+            .map(|target| {
+                let dir_name = target_dir_name(target, &targets);
+
+                ApiTree {
+                    markdown_path: api_dir.join(&dir_name),
+                    url: format!("{}/api/{}", base_url, dir_name),
+                }
+            })
+            .collect()
+    };
 
-    generate_and_load_analysis(config, &target, log)?;
+    // links between crates go by crate name. libraries come first, so if a binary has the same
+    // crate name as the library, links go to the library.
+    let mut crate_urls = HashMap::new();
+    for (target, tree) in targets.iter().zip(&trees) {
+        crate_urls
+            .entry(target.crate_name())
+            .or_insert_with(|| tree.url.clone());
+    }
+
+    let mut documented = Vec::new();
+
+    if targets.len() == 1 {
+        let overviews = document_target(
+            config,
+            log,
+            &targets[0],
+            &trees[0],
+            0,
+            &crates,
+            &crate_urls,
+            files,
+        )?;
+        documented.push((&targets[0], overviews));
+    } else {
+        let mut index = Vec::new();
+
+        for (i, target) in targets.iter().enumerate() {
+            let tree = &trees[i];
+
+            debug!(log, "creating api dir for target";
+            o!("target" => target.name.clone(), "dir" => tree.markdown_path.display()));
+            files.create_dir(&tree.markdown_path)?;
+
+            let overviews = document_target(
+                config,
+                log,
+                target,
+                tree,
+                i,
+                &crates,
+                &crate_urls,
+                files,
+            )?;
+
+            index.push(json!({
+                "name": target.name,
+                "kind": match target.kind {
+                    TargetKind::Library => "library",
+                    TargetKind::Binary => "binary",
+                },
+                "url": format!("{}/index.html", tree.url),
+            }));
+            documented.push((target, overviews));
+        }
+
+        let markdown_path = config.api_readme_path();
+
+        debug!(log, "creating README.md index for api";
+        o!("file" => markdown_path.display()));
+        files.write(
+            &markdown_path,
+            &config
+                .handlebars()
+                .render("api-index", &json!({ "targets": index }))?,
+        )?;
+    }
+
+    // the overview pages cover every target, with a section for each if there's more than one
+    write_overview(
+        &config.api_module_overview_path(),
+        "Module overview",
+        &documented,
+        |overviews| &overviews.modules,
+        files,
+    )?;
+    write_overview(
+        &config.api_struct_overview_path(),
+        "Struct overview",
+        &documented,
+        |overviews| &overviews.structs,
+        files,
+    )?;
+    write_overview(
+        &config.api_trait_overview_path(),
+        "Trait overview",
+        &documented,
+        |overviews| &overviews.traits,
+        files,
+    )?;
+
+    Ok(())
+}
+
+fn write_overview<F>(
+    markdown_path: &Path,
+    title: &str,
+    documented: &[(&Target, Overviews)],
+    entries: F,
+    files: &mut GeneratedFiles,
+) -> Result<()>
+where
+    F: Fn(&Overviews) -> &String,
+{
+    let mut contents = format!("# {}\n\n", title);
+
+    if documented.len() == 1 {
+        contents.push_str(entries(&documented[0].1));
+    } else {
+        for &(target, ref overviews) in documented {
+            contents.push_str(&format!("## `{}`\n\n", target.name));
+            contents.push_str(entries(overviews));
+            contents.push('\n');
+        }
+    }
+
+    files.write(markdown_path, &contents)
+}
+
+/// Write the API docs for a single target into `tree`, returning the entries for its overviews.
+///
+/// `crates` holds the save-analysis data for every target, with this one's at `index`, and
+/// `crate_urls` maps their crate names to the urls of their API docs.
+fn document_target(
+    config: &Config,
+    log: &Logger,
+    target: &Target,
+    tree: &ApiTree,
+    index: usize,
+    crates: &[SaveAnalysis],
+    crate_urls: &HashMap<String, String>,
+    files: &mut GeneratedFiles,
+) -> Result<Overviews> {
+    let log = log.new(o!("target" => target.name.clone()));
+
+    let save_analysis = &crates[index];
 
     let host = config.host();
     let crate_name = &target.crate_name();
 
-    let roots = host.def_roots()?;
-
     // we want to keep track of all modules/structs/traits for the module overview page
     let mut module_set = HashSet::new();
     let mut struct_set = HashSet::new();
     let mut trait_set = HashSet::new();
 
-    // we also want to track the files/folders that we created so that `update` can clean up what
-    // was left
-    let mut file_set = HashSet::new();
-
-    let id = roots.iter().find(|&&(_, ref name)| name == crate_name);
-    let root_id = match id {
-        Some(&(id, _)) => id,
-        _ => {
-            return Err(error::CrateErr {
-                crate_name: crate_name.to_string(),
-            }.into())
-        }
-    };
-
+    let root_id = root_id(config, target)?;
     let root_def = host.get_def(root_id)?;
 
-    let markdown_path = config.api_readme_path();
+    let markdown_path = tree.markdown_path.join("README.md");
 
     debug!(log, "creating README.md for api";
     o!("file" => markdown_path.display()));
-    file_set.insert(markdown_path.clone());
-    let mut file = File::create(markdown_path)?;
-
-    file.write_all(
-        config
-            .handlebars()
-            .render(
-                "api",
-                &json!({"name": crate_name, "docs": strip_leading_space(&root_def.docs)}),
-            )?
-            .as_bytes(),
+    files.write(
+        &markdown_path,
+        &config.handlebars().render(
+            "api",
+            &json!({"name": crate_name, "docs": strip_leading_space(&root_def.docs)}),
+        )?,
     )?;
 
     let ids = host.for_each_child_def(root_id, |id, _def| id).unwrap();
@@ -144,26 +319,93 @@ pub fn create(config: &Config, log: &Logger) -> Result<HashSet<PathBuf>> {
             };
 
             let containing_path = name_to_path(&def.qualname);
-            let containing_path = api_dir.join(containing_path);
+            let containing_path = tree.markdown_path.join(containing_path);
 
             debug!(log, "creating"; o!("dir" => containing_path.display()));
-            fs::create_dir_all(&containing_path)?;
-            file_set.insert(containing_path.clone());
+            files.create_dir(&containing_path)?;
 
             let markdown_path = containing_path.join(&format!("{}.md", def.name));
-            file_set.insert(markdown_path.clone());
             debug!(log, "writing"; o!("file" => markdown_path.display()));
 
-            let mut file = File::create(markdown_path)?;
+            let impls = match def.kind {
+                DefKind::Struct | DefKind::Enum => {
+                    render_impls(save_analysis.inherent_impls(&def.qualname))
+                }
+                _ => Vec::new(),
+            };
+
+            let fields: Vec<_> = match def.kind {
+                DefKind::Struct => save_analysis
+                    .fields(&def.qualname)
+                    .into_iter()
+                    .map(|field| {
+                        json!({
+                            "name": field.def.name,
+                            "type": table_cell(&field.def.value),
+                            "visibility": field.visibility,
+                            "docs": table_cell(&strip_leading_space(&field.def.docs)),
+                        })
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            };
+
+            let variants: Vec<_> = match def.kind {
+                DefKind::Enum => save_analysis
+                    .variants(&def.qualname)
+                    .into_iter()
+                    .map(|variant| {
+                        json!({
+                            "name": variant.def.name,
+                            "payload": variant.payload,
+                            "docs": strip_leading_space(&variant.def.docs),
+                        })
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            };
 
-            file.write_all(
-                config
-                    .handlebars()
-                    .render(
-                        template_name,
-                        &json!({"name": def.name, "docs": strip_leading_space(&def.docs), "signature": def.value}),
-                    )?
-                    .as_bytes(),
+            let traits = match def.kind {
+                DefKind::Struct | DefKind::Enum => {
+                    render_related(crate_urls, save_analysis.trait_impls(&def.qualname, crates))
+                }
+                _ => Vec::new(),
+            };
+
+            let (provided, required): (Vec<_>, Vec<_>) = match def.kind {
+                DefKind::Trait => save_analysis
+                    .trait_items(&def.qualname)
+                    .into_iter()
+                    .partition(|item| item.provided),
+                _ => (Vec::new(), Vec::new()),
+            };
+            let provided: Vec<_> = provided.iter().map(|item| render_item(item.def)).collect();
+            let required: Vec<_> = required.iter().map(|item| render_item(item.def)).collect();
+
+            let implementors = match def.kind {
+                DefKind::Trait => {
+                    render_related(crate_urls, save_analysis.implementors(&def.qualname, crates))
+                }
+                _ => Vec::new(),
+            };
+
+            files.write(
+                &markdown_path,
+                &config.handlebars().render(
+                    template_name,
+                    &json!({
+                        "name": def.name,
+                        "docs": strip_leading_space(&def.docs),
+                        "signature": def.value,
+                        "fields": fields,
+                        "variants": variants,
+                        "impls": impls,
+                        "traits": traits,
+                        "required": required,
+                        "provided": provided,
+                        "implementors": implementors,
+                    }),
+                )?,
             )?;
         }
 
@@ -186,7 +428,7 @@ pub fn create(config: &Config, log: &Logger) -> Result<HashSet<PathBuf>> {
             possible_children: &HashSet<analysis::Id>,
             host: &analysis::AnalysisHost,
         ) {
-            let children: Vec<&analysis::Id> = possible_children
+            let mut children: Vec<&analysis::Id> = possible_children
                 .iter()
                 .filter(|child| {
                     let def = host.get_def(**child).unwrap();
@@ -194,6 +436,9 @@ pub fn create(config: &Config, log: &Logger) -> Result<HashSet<PathBuf>> {
                 })
                 .collect();
 
+            // sets have no order, but the overview should be the same every time it's generated
+            children.sort_by_key(|child| host.get_def(**child).unwrap().name);
+
             // the base case!
             if children.is_empty() {
                 return;
@@ -213,47 +458,26 @@ pub fn create(config: &Config, log: &Logger) -> Result<HashSet<PathBuf>> {
 
         add_children(&mut krate, &module_set, host);
 
-        // time to write out the markdown
-
-        let markdown_path = config.api_module_overview_path();
+        // time to write out the overview entries
 
-        file_set.insert(markdown_path.clone());
-        let mut file = File::create(markdown_path)?;
-
-        file.write_all(b"# Module overview\n\n")?;
+        let mut modules = String::new();
 
         fn print_tree(
             node: &Module,
             depth: usize,
             host: &analysis::AnalysisHost,
-            file: &mut File,
-            config: &Config,
+            out: &mut String,
+            tree: &ApiTree,
+            crate_name: &str,
         ) {
             let def = host.get_def(node.id).unwrap();
 
-            let name = if def.name.is_empty() {
-                "doxidize".to_string()
-            } else {
-                def.name
-            };
-
-            // skip the initial crate name
-            let mut path: Vec<_> = def.qualname.split("::").skip(1).collect();
-            // pop off the final bit of the path, as that's the name, not the path itself
-            path.pop();
-
-            // the web uses / for paths, not \ or /
-            let path = path.join("/");
-            let base_url = if config.base_url().is_empty() {
-                String::new()
+            // the crate root has no name of its own, and its page is the README
+            let (name, url) = if def.name.is_empty() {
+                (crate_name.to_string(), format!("{}/index.html", tree.url))
             } else {
-                format!("/{}", config.base_url())
-            };
-
-            let url = if path.is_empty() {
-                format!("{}/api/{}.html", base_url, name)
-            } else {
-                format!("{}/api/{}/{}.html", base_url, path, name)
+                let url = qualname_to_url(&tree.url, &def.qualname);
+                (def.name, url)
             };
 
             let line = format!(
@@ -265,103 +489,315 @@ pub fn create(config: &Config, log: &Logger) -> Result<HashSet<PathBuf>> {
                 name,
                 url,
             );
-            file.write_all(line.as_bytes()).unwrap();
+            out.push_str(&line);
 
             if node.children.is_empty() {
                 return;
             }
 
             for child in &node.children {
-                print_tree(child, depth + 1, host, file, config);
+                print_tree(child, depth + 1, host, out, tree, crate_name);
             }
         }
 
-        print_tree(&krate, 0, host, &mut file, config);
+        print_tree(&krate, 0, host, &mut modules, tree, crate_name);
 
         // struct overview
 
-        let markdown_path = config.api_struct_overview_path();
+        let mut structs = String::new();
 
-        file_set.insert(markdown_path.clone());
-        let mut file = File::create(markdown_path)?;
+        for def in sorted_defs(struct_set, host) {
+            let url = qualname_to_url(&tree.url, &def.qualname);
 
-        file.write_all(b"# Struct overview\n\n")?;
+            structs.push_str(&format!("* [{}]({})\n", def.name, url));
+        }
 
-        for id in struct_set {
-            let def = host.get_def(id).unwrap();
+        // trait overview
 
-            let name = if def.name.is_empty() {
-                "doxidize".to_string()
-            } else {
-                def.name
-            };
+        let mut traits = String::new();
 
-            // skip the initial crate name
-            let mut path: Vec<_> = def.qualname.split("::").skip(1).collect();
-            // pop off the final bit of the path, as that's the name, not the path itself
-            path.pop();
+        for def in sorted_defs(trait_set, host) {
+            let url = qualname_to_url(&tree.url, &def.qualname);
 
-            // the web uses / for paths, not \ or /
-            let path = path.join("/");
-            let base_url = if config.base_url().is_empty() {
-                String::from("")
-            } else {
-                format!("/{}", config.base_url())
-            };
+            traits.push_str(&format!("* [{}]({})\n", def.name, url));
+        }
 
-            let url = if path.is_empty() {
-                format!("{}/api/{}.html", base_url, name)
-            } else {
-                format!("{}/api/{}/{}.html", base_url, path, name)
-            };
+        info!(log, "done");
 
-            file.write_all(format!("* [{}]({})\n", name, url).as_bytes())?
-        }
+        Ok(Overviews {
+            modules,
+            structs,
+            traits,
+        })
+    }
+}
 
-        // trait overview
+/// Find the id of the root module of a target, once its analysis has been loaded.
+pub fn root_id(config: &Config, target: &Target) -> Result<analysis::Id> {
+    let host = config.host();
+    let crate_name = target.crate_name();
 
-        let markdown_path = config.api_trait_overview_path();
+    let roots = host.def_roots()?;
 
-        file_set.insert(markdown_path.clone());
-        let mut file = File::create(markdown_path)?;
+    // a package's library and binaries can share a crate name, so check which file each root is
+    // in as well
+    let candidates: Vec<analysis::Id> = roots
+        .iter()
+        .filter(|&&(_, ref name)| *name == crate_name)
+        .map(|&(id, _)| id)
+        .collect();
+
+    let id = candidates
+        .iter()
+        .cloned()
+        .find(|id| {
+            host.get_def(*id)
+                .map(|def| config.root_path().join(&def.span.file) == target.src_path)
+                .unwrap_or(false)
+        })
+        .or_else(|| candidates.first().cloned());
+
+    match id {
+        Some(id) => Ok(id),
+        _ => Err(error::CrateErr { crate_name }.into()),
+    }
+}
 
-        file.write_all(b"# Trait overview\n\n")?;
+/// A public item, with what `coverage` and `api-diff` need to know about it.
+#[derive(Debug)]
+pub struct PublicItem {
+    pub kind: &'static str,
 
-        for id in trait_set {
-            let def = host.get_def(id).unwrap();
+    /// The qualname, like `example::Point::new`
+    pub qualname: String,
 
-            let name = if def.name.is_empty() {
-                "doxidize".to_string()
-            } else {
-                def.name
-            };
+    /// The qualname of the module the item is in, or of the crate, for the crate itself
+    pub module: String,
 
-            // skip the initial crate name
-            let mut path: Vec<_> = def.qualname.split("::").skip(1).collect();
-            // pop off the final bit of the path, as that's the name, not the path itself
-            path.pop();
+    /// The item's declaration, like `fn new() -> Point`, or its file, for modules
+    pub signature: String,
 
-            // the web uses / for paths, not \ or /
-            let path = path.join("/");
-            let base_url = if config.base_url().is_empty() {
-                String::from("")
-            } else {
-                format!("/{}", config.base_url())
-            };
+    pub docs: String,
+    pub file: PathBuf,
+    pub line: u32,
+}
 
-            let url = if path.is_empty() {
-                format!("{}/api/{}.html", base_url, name)
-            } else {
-                format!("{}/api/{}/{}.html", base_url, path, name)
-            };
+/// The public items in a target, along with what kind of item each one is. The crate itself comes
+/// first, and then everything in it, breadth first, with the fields, variants and associated items
+/// of each type and trait right after it.
+pub fn public_items(config: &Config, target: &Target) -> Result<Vec<PublicItem>> {
+    let host = config.host();
+    let analysis = SaveAnalysis::load(config, target)?;
+    let crate_name = target.crate_name();
+
+    let mut items = Vec::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(root_id(config, target)?);
+
+    while let Some(id) = queue.pop_front() {
+        host.for_each_child_def(id, |id, _def| {
+            queue.push_back(id);
+        })?;
+
+        let def = host.get_def(id)?;
+
+        let kind = match def.kind {
+            DefKind::Mod => "module",
+            DefKind::Struct => "struct",
+            DefKind::Enum => "enum",
+            DefKind::Trait => "trait",
+            DefKind::Function => "function",
+            DefKind::Type => "type",
+            DefKind::Static => "static",
+            DefKind::Const => "const",
+            _ => continue,
+        };
+
+        // the crate itself has no module around it, so it counts as part of itself
+        let module = match def.qualname.rfind("::") {
+            Some(index) => def.qualname[..index].to_string(),
+            None => crate_name.clone(),
+        };
+
+        let item = PublicItem {
+            kind,
+            qualname: def.qualname,
+            module,
+            signature: def.value,
+            docs: def.docs,
+            file: def.span.file,
+            line: def.span.range.row_start.one_indexed().0,
+        };
+
+        let members = members(&analysis, &item);
+
+        items.push(item);
+        items.extend(members);
+    }
+
+    Ok(items)
+}
+
+/// The public fields, variants and associated items of a type or trait.
+///
+/// Methods in inherent impls have no parent as far as `AnalysisHost` is concerned, so these all
+/// come from the raw data instead.
+fn members(analysis: &SaveAnalysis, parent: &PublicItem) -> Vec<PublicItem> {
+    let member = |kind, def: &analysis_data::Def, signature| PublicItem {
+        kind,
+        qualname: format!("{}::{}", parent.qualname, def.name),
+        module: parent.module.clone(),
+        signature,
+        docs: def.docs.clone(),
+        file: def.span.file_name.clone(),
+        line: def.span.line_start.0,
+    };
+
+    let mut members = Vec::new();
 
-            file.write_all(format!("* [{}]({})\n", name, url).as_bytes())?
+    match parent.kind {
+        "struct" => {
+            for field in analysis.fields(&parent.qualname) {
+                // a public struct can still have private fields, which aren't part of the API
+                if field.visibility == "pub" {
+                    let signature = format!("{}: {}", field.def.name, field.def.value);
+                    members.push(member("field", field.def, signature));
+                }
+            }
+        }
+        "enum" => {
+            for variant in analysis.variants(&parent.qualname) {
+                let signature = format!("{}{}", variant.def.name, variant.payload);
+                members.push(member("variant", variant.def, signature));
+            }
+        }
+        "trait" => {
+            for item in analysis.trait_items(&parent.qualname) {
+                let (kind, signature) = associated_item(item.def);
+                members.push(member(kind, item.def, signature));
+            }
         }
+        _ => return members,
+    }
 
-        info!(log, "done");
+    for imp in analysis.inherent_impls(&parent.qualname) {
+        for def in imp.items {
+            let (kind, signature) = associated_item(def);
+            members.push(member(kind, def, signature));
+        }
+    }
+
+    members
+}
+
+/// Look up the defs with these ids, in order of name, so that the overviews are the same every
+/// time they're generated.
+fn sorted_defs(
+    ids: HashSet<analysis::Id>,
+    host: &analysis::AnalysisHost,
+) -> Vec<analysis::Def> {
+    let mut defs: Vec<_> = ids.into_iter().map(|id| host.get_def(id).unwrap()).collect();
+    defs.sort_by(|a, b| (&a.name, &a.qualname).cmp(&(&b.name, &b.qualname)));
+    defs
+}
+
+/// Squeeze some markdown into a single cell of a table.
+///
+/// Table rows are a single line, so paragraphs are separated with line breaks instead, and any `|`
+/// is escaped so that it doesn't end the cell.
+fn table_cell(markdown: &str) -> String {
+    let paragraphs: Vec<String> = markdown
+        .split("\n\n")
+        .map(|paragraph| paragraph.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect();
+
+    paragraphs.join("<br><br>").replace('|', "\\|")
+}
+
+/// Turn impl blocks into the json that the `struct` and `enum` templates expect.
+fn render_impls(impls: Vec<save_analysis::ImplBlock>) -> Vec<serde_json::Value> {
+    impls
+        .into_iter()
+        .map(|imp| {
+            let items: Vec<_> = imp.items.iter().map(|def| render_item(def)).collect();
+
+            json!({"header": imp.header, "items": items})
+        })
+        .collect()
+}
+
+/// Turn an associated item, from either an impl or a trait, into json for the templates.
+fn render_item(def: &analysis_data::Def) -> serde_json::Value {
+    let (kind, signature) = associated_item(def);
+
+    json!({
+        "kind": kind,
+        "name": def.name,
+        "signature": signature,
+        "docs": strip_leading_space(&def.docs),
+    })
+}
+
+/// What kind of associated item this is, and its signature.
+fn associated_item(def: &analysis_data::Def) -> (&'static str, String) {
+    match def.kind {
+        DefKind::Method => (
+            "method",
+            save_analysis::method_signature(&def.name, &def.value),
+        ),
+        DefKind::Const => ("associated constant", def.value.clone()),
+        _ => ("associated type", def.value.clone()),
     }
+}
+
+/// Turn related items into the json that the templates expect, linking the ones we have pages for.
+///
+/// Items can be from any of the crates being documented, so `crate_urls` maps each crate name to
+/// the url of its API docs.
+fn render_related(
+    crate_urls: &HashMap<String, String>,
+    items: Vec<save_analysis::RelatedItem>,
+) -> Vec<serde_json::Value> {
+    items
+        .into_iter()
+        .map(|item| {
+            let url = item.qualname.as_ref().and_then(|qualname| {
+                let crate_name = qualname.split("::").next()?;
+                let tree_url = crate_urls.get(crate_name)?;
+
+                Some(qualname_to_url(tree_url, qualname))
+            });
+
+            json!({"name": item.name, "url": url})
+        })
+        .collect()
+}
+
+/// Returns the url of the generated page for the item with this qualname.
+///
+/// `tree_url` is the url of the API docs the item is part of, like `/doxidize/api`.
+fn qualname_to_url(tree_url: &str, qualname: &str) -> String {
+    // skip the initial crate name
+    let path: Vec<_> = qualname.split("::").skip(1).collect();
 
-    Ok(file_set)
+    // the web uses / for paths, not \ or /
+    format!("{}/{}.html", tree_url, path.join("/"))
+}
+
+/// Returns the name of the directory that a target's API docs go in, when there's more than one.
+///
+/// A binary with the same name as the library gets a suffix, so they don't end up in the same place.
+pub fn target_dir_name(target: &Target, targets: &[Target]) -> String {
+    let shares_name_with_library = targets.iter().any(|other| {
+        other.kind == TargetKind::Library && other.name == target.name
+    });
+
+    if target.kind == TargetKind::Binary && shares_name_with_library {
+        format!("{}-bin", target.name)
+    } else {
+        target.name.clone()
+    }
 }
 
 fn name_to_path(name: &str) -> PathBuf {
@@ -379,13 +815,16 @@ fn name_to_path(name: &str) -> PathBuf {
     path
 }
 
-/// Generate save analysis data of a crate to be used later by the RLS library later and load it
-/// into the analysis host.
-fn generate_and_load_analysis(config: &Config, target: &Target, log: &Logger) -> Result<()> {
+/// Generate save analysis data of a crate's targets to be used later by the RLS library later and
+/// load it into the analysis host.
+pub fn generate_and_load_analysis(config: &Config, targets: &[Target], log: &Logger) -> Result<()> {
     let log = log.new(o!("step" => "analyzing your source code"));
     info!(log, "starting");
 
-    cargo::generate_analysis(config, target)?;
+    for target in targets {
+        debug!(log, "analyzing target"; o!("target" => target.name.clone()));
+        cargo::generate_analysis(config, target)?;
+    }
 
     let root_path = config.root_path();
     debug!(log, "analysis complete, loading");
@@ -397,6 +836,23 @@ fn generate_and_load_analysis(config: &Config, target: &Target, log: &Logger) ->
 
 #[cfg(test)]
 mod tests {
+    mod table_cell {
+        use super::super::table_cell;
+
+        #[test]
+        fn joins_lines_and_paragraphs() {
+            assert_eq!(
+                table_cell("The x coordinate,\nin pixels.\n\nCan be negative."),
+                "The x coordinate, in pixels.<br><br>Can be negative."
+            );
+        }
+
+        #[test]
+        fn escapes_pipes() {
+            assert_eq!(table_cell("Either `a | b`"), "Either `a \\| b`");
+        }
+    }
+
     mod name_to_path {
         use std::path::PathBuf;
         use super::super::name_to_path;
@@ -434,4 +890,62 @@ mod tests {
             assert_eq!(path, name_to_path(name));
         }
     }
+
+    mod qualname_to_url {
+        use super::super::qualname_to_url;
+
+        #[test]
+        fn top_level_item() {
+            assert_eq!(
+                qualname_to_url("/api", "doxidize::Config"),
+                "/api/Config.html"
+            );
+        }
+
+        #[test]
+        fn nested_item_with_base_url() {
+            assert_eq!(
+                qualname_to_url("/doxidize/api/cli", "cli::examples::Point"),
+                "/doxidize/api/cli/examples/Point.html"
+            );
+        }
+    }
+
+    mod target_dir_name {
+        use std::path::PathBuf;
+
+        use cargo::{Target, TargetKind};
+        use super::super::target_dir_name;
+
+        fn target(kind: TargetKind, name: &str) -> Target {
+            Target {
+                kind,
+                name: name.into(),
+                package: "doxidize".into(),
+                src_path: PathBuf::new(),
+            }
+        }
+
+        #[test]
+        fn uses_target_name() {
+            let targets = vec![
+                target(TargetKind::Library, "doxidize"),
+                target(TargetKind::Binary, "helper"),
+            ];
+
+            assert_eq!(target_dir_name(&targets[0], &targets), "doxidize");
+            assert_eq!(target_dir_name(&targets[1], &targets), "helper");
+        }
+
+        #[test]
+        fn binary_sharing_library_name_gets_suffix() {
+            let targets = vec![
+                target(TargetKind::Library, "doxidize"),
+                target(TargetKind::Binary, "doxidize"),
+            ];
+
+            assert_eq!(target_dir_name(&targets[0], &targets), "doxidize");
+            assert_eq!(target_dir_name(&targets[1], &targets), "doxidize-bin");
+        }
+    }
 }
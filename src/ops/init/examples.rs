@@ -0,0 +1,78 @@
+use slog::Logger;
+
+use std::fs::{self, File};
+use std::io::prelude::*;
+
+use ops::generated::GeneratedFiles;
+use Config;
+use Result;
+
+/// Generate a page for each example in the examples directory, writing them with `files`.
+///
+/// Returns the ids of the pages, which are the examples' file names, in order.
+pub fn create(config: &Config, log: &Logger, files: &mut GeneratedFiles) -> Result<Vec<String>> {
+    let examples_dir = config.examples_markdown_path();
+    debug!(log, "creating examples dir";
+    o!("dir" => examples_dir.display()));
+    files.create_dir(&examples_dir)?;
+
+    let mut ids = Vec::new();
+
+    if !config.examples_path().is_dir() {
+        return Ok(ids);
+    }
+
+    let mut paths = Vec::new();
+
+    for entry in fs::read_dir(config.examples_path())? {
+        paths.push(entry?.path());
+    }
+
+    // the order of read_dir depends on the platform, and this decides the order of the menu
+    paths.sort();
+
+    for path in paths {
+        // we want only files
+        if !path.is_file() {
+            continue;
+        }
+        trace!(log, "file is a file, continuing");
+
+        if let Some(extension) = path.extension() {
+            // we only want .rs files
+            if extension != "rs" {
+                continue;
+            }
+        } else {
+            // we don't want files with no extension
+            continue;
+        }
+        trace!(log, "file is a rust file, continuing");
+
+        // we certainly have a file name, since we're looping over real files
+        let file_name = path.file_name().unwrap();
+        let rust_file = config.examples_path().join(file_name);
+
+        trace!(log, "reading file";
+        "file" => rust_file.display());
+        let mut file = File::open(&rust_file)?;
+        let mut code = String::new();
+        file.read_to_string(&mut code)?;
+
+        let markdown_path = examples_dir.join(file_name).with_extension("md");
+
+        trace!(log, "rendering to markdown";
+        "file" => path.display(), "file" => markdown_path.display());
+        let name = file_name.to_str().unwrap();
+
+        let rendered = config
+            .handlebars()
+            .render("example", &json!({"name": name, "code": code}))?;
+
+        files.write(&markdown_path, &rendered)?;
+
+        ids.push(name.to_string())
+    }
+
+    Ok(ids)
+}
@@ -1,10 +1,14 @@
 pub mod api;
+pub mod examples;
 
 use slog::Logger;
+use toml;
 
-use std::fs::{self, File, OpenOptions};
+use std::fs::{self, OpenOptions};
 use std::io::prelude::*;
 
+use ops::generated::GeneratedFiles;
+use ops::menu;
 use Config;
 use Result;
 
@@ -28,11 +32,14 @@ pub fn init(config: &Config, log: &Logger) -> Result<()> {
     create_docs_readme(config, &log)?;
     create_doxidize_config(config, &log)?;
 
-    let examples = create_examples(config, &log)?;
+    let mut files = GeneratedFiles::new();
 
-    create_menu_toml(config, &log, examples)?;
+    let examples = examples::create(config, &log, &mut files)?;
 
-    api::create(config, &log)?;
+    create_menu_toml(config, &log, &examples)?;
+
+    api::create(config, &log, &mut files)?;
+    files.check_conflicts()?;
 
     info!(log, "done");
     Ok(())
@@ -98,95 +105,57 @@ fn create_doxidize_config(config: &Config, log: &Logger) -> Result<()> {
     let doxidize_config = config.config_path();
 
     debug!(log, "creating Doxidize.toml"; o!("file" => doxidize_config.display()));
-    OpenOptions::new()
+    let mut file = OpenOptions::new()
         .create(true)
         .append(true)
         .open(doxidize_config)?;
 
-    Ok(())
-}
+    // if the docs don't go in the usual places, record where they are, so that every other
+    // command can find them without being told again
+    let settings = [
+        ("source", config.markdown_path(), config.root_path().join("docs")),
+        (
+            "output",
+            config.output_path(),
+            config.root_path().join("target").join("docs"),
+        ),
+    ];
+
+    let mut docs_table = String::new();
+
+    for &(key, ref path, ref default) in &settings {
+        if path == default {
+            continue;
+        }
 
-fn create_menu_toml(config: &Config, log: &Logger, examples: Vec<String>) -> Result<()> {
-    let menu = config.menu_path();
+        // relative paths are relative to the project root, so we use one if we can
+        let path = path.strip_prefix(config.root_path()).unwrap_or(path.as_path());
+        let value = toml::Value::String(path.to_string_lossy().into_owned());
 
-    debug!(log, "creating Menu.toml"; o!("file" => menu.display()));
-    let mut menu = OpenOptions::new().create(true).append(true).open(menu)?;
-    menu.write_all(
-        br#""Getting Started" = [
-    "overview",
-]
-
-"Examples" = [
-"#,
-    ).expect("could not write to Menu.toml");
-
-    for example in examples {
-        menu.write_all(format!("    \"{}\",\n", example).as_bytes())
-            .expect("could not write to Menu.toml");
+        docs_table.push_str(&format!("{} = {}\n", key, value));
     }
 
-    menu.write_all(b"]").expect("could not write to Menu.toml");
+    if !docs_table.is_empty() {
+        trace!(log, "recording custom paths in Doxidize.toml");
+        file.write_all(format!("[docs]\n{}", docs_table).as_bytes())?;
+    }
 
     Ok(())
 }
 
-fn create_examples(config: &Config, log: &Logger) -> Result<Vec<String>> {
-    let examples_dir = config.examples_markdown_path();
-    debug!(log, "creating examples dir";
-    o!("dir" => examples_dir.display()));
-    fs::create_dir_all(&examples_dir)?;
-
-    let mut ids = Vec::new();
-
-    if config.examples_path().is_dir() {
-        for entry in fs::read_dir(config.examples_path())? {
-            let entry = entry?;
-            let path = entry.path();
-
-            // we want only files
-            if !path.is_file() {
-                continue;
-            }
-            trace!(log, "file is a file, continuing");
-
-            if let Some(extension) = path.extension() {
-                // we only want .rs files
-                if extension != "rs" {
-                    continue;
-                }
-            } else {
-                // we don't want files with no extension
-                continue;
-            }
-            trace!(log, "file is a rust file, continuing");
-
-            // we certainly have a file name, since we're looping over real files
-            let file_name = path.file_name().unwrap();
-            let rust_file = config.examples_path().join(file_name);
-
-            trace!(log, "reading file";
-            "file" => rust_file.display());
-            let mut file = File::open(&rust_file)?;
-            let mut code = String::new();
-            file.read_to_string(&mut code)?;
-
-            let markdown_path = examples_dir.join(file_name).with_extension("md");
-
-            trace!(log, "rendering to markdown";
-            "file" => path.display(), "file" => markdown_path.display());
-            let mut file = File::create(markdown_path)?;
-            let name = file_name.to_str().unwrap();
-
-            file.write_all(
-                config
-                    .handlebars()
-                    .render("example", &json!({"name": name, "code": code}))?
-                    .as_bytes(),
-            )?;
-
-            ids.push(name.to_string())
-        }
-    }
+fn create_menu_toml(config: &Config, log: &Logger, examples: &[String]) -> Result<()> {
+    let menu = config.menu_path();
+
+    debug!(log, "creating Menu.toml"; o!("file" => menu.display()));
+    let mut file = OpenOptions::new().create(true).append(true).open(menu)?;
 
-    Ok(ids)
+    let contents = format!(
+        "{}\n{}",
+        menu::section("Getting Started", &[String::from("overview")]),
+        menu::section("Examples", examples)
+    );
+
+    file.write_all(contents.as_bytes()).expect("could not write to Menu.toml");
+
+    Ok(())
 }
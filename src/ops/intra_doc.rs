@@ -0,0 +1,488 @@
+//! Links from markdown to API items.
+//!
+//! Guide pages and doc comments can link to an item by its path, with `[Point](::examples::Point)`,
+//! or with just its name in code, as in `` [`Point`] ``. These are turned into ordinary links to
+//! the item's page before the markdown is rendered.
+
+/// The pages of the generated API docs, by the path of the item each one documents.
+#[derive(Debug, Default, Hash)]
+pub struct ApiIndex {
+    /// The path of each item, without the crate name if there's only one target, and the url of
+    /// its page relative to the root of the site
+    items: Vec<(Vec<String>, String)>,
+
+    /// The names of the documented crates, along with how the paths of their items start in
+    /// `items`: not at all with only one target, and with the name of the target's directory
+    /// otherwise
+    crates: Vec<(String, Vec<String>)>,
+}
+
+/// An intra-doc link that couldn't be resolved.
+#[derive(Debug, PartialEq)]
+pub struct BrokenLink {
+    /// The line the link is on, starting at one
+    pub line: usize,
+
+    pub message: String,
+
+    /// Whether this was written as an explicit link, like `[Point](::examples::Point)`.
+    ///
+    /// Code in brackets, like `` [`u8`] ``, may not have been meant as a link at all, so when
+    /// those don't resolve it's only worth a warning. The same goes for explicit links to items
+    /// in other crates, which we have no pages for.
+    pub explicit: bool,
+}
+
+impl ApiIndex {
+    pub fn insert(&mut self, path: Vec<String>, url: String) {
+        self.items.push((path, url));
+    }
+
+    /// Add a documented crate, so that paths that start with its name can be resolved, and so
+    /// that links into it can be told from links to other crates, like `::std::fmt::Display`.
+    pub fn add_crate(&mut self, name: String, path: Vec<String>) {
+        self.crates.push((name, path));
+    }
+
+    /// Whether a path points into one of the documented crates, rather than some other crate.
+    ///
+    /// Paths can start from the root of a crate without naming it, so a path that starts with
+    /// one of the indexed items, like `examples::Point`, counts too.
+    fn is_local(&self, path: &str) -> bool {
+        let first = path.trim_left_matches("::").split("::").next().unwrap_or("");
+
+        first == "crate"
+            || self.crates.iter().any(|&(ref name, _)| name == first)
+            || self.items
+                .iter()
+                .any(|&(ref item, _)| item.first().map(|name| &name[..]) == Some(first))
+    }
+
+    /// Find the url of the item a path refers to. The path can be cut short at the front, so
+    /// `Point` finds `examples::Point`, as long as there's only one item it could mean.
+    fn resolve(&self, path: &str) -> Result<&str, String> {
+        let path = path.trim_left_matches("::");
+        let path = if path.starts_with("crate::") {
+            &path["crate::".len()..]
+        } else {
+            path
+        };
+
+        let mut segments: Vec<String> = path.split("::").map(|s| s.to_string()).collect();
+
+        // a crate's name is written with underscores, but its items are indexed under the name of
+        // its directory, if they're under anything at all. libraries were added first, so they
+        // win over binaries with the same crate name.
+        let prefix = self.crates
+            .iter()
+            .find(|&&(ref name, _)| *name == segments[0])
+            .map(|&(_, ref prefix)| prefix.clone());
+
+        if let Some(prefix) = prefix {
+            segments = prefix.into_iter().chain(segments.into_iter().skip(1)).collect();
+        }
+
+        let candidates: Vec<_> = self.items
+            .iter()
+            .filter(|&&(ref item, _)| {
+                // the root of a crate is only what its whole path says it is
+                if segments.is_empty() {
+                    item.is_empty()
+                } else {
+                    item.ends_with(&segments)
+                }
+            })
+            .collect();
+
+        match candidates.len() {
+            0 => Err(format!("no API item is named `{}`", path)),
+            1 => Ok(&candidates[0].1[..]),
+            _ => {
+                let names: Vec<_> = candidates
+                    .iter()
+                    .map(|&&(ref item, _)| format!("`{}`", item.join("::")))
+                    .collect();
+
+                Err(format!(
+                    "`{}` could be any of {}; use more of its path",
+                    path,
+                    names.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+/// Replace the intra-doc links in some markdown with links to the pages of the items.
+///
+/// `prefix` is put in front of each url, to get from the page back to the root of the site.
+/// `first_line` is the line of the file that the markdown starts on, so that problems can point
+/// at the right place even after the front matter has been removed.
+pub fn resolve_links(
+    markdown: &str,
+    index: &ApiIndex,
+    prefix: &str,
+    first_line: usize,
+) -> (String, Vec<BrokenLink>) {
+    let mut resolved = String::with_capacity(markdown.len());
+    let mut broken = Vec::new();
+
+    let mut fence: Option<&str> = None;
+
+    for (number, line) in markdown.split('\n').enumerate() {
+        if number > 0 {
+            resolved.push('\n');
+        }
+
+        // links inside code blocks are just code
+        let trimmed = line.trim_left();
+        match fence {
+            Some(marker) => {
+                if trimmed.starts_with(marker) {
+                    fence = None;
+                }
+
+                resolved.push_str(line);
+                continue;
+            }
+            None => {
+                if trimmed.starts_with("```") {
+                    fence = Some("```");
+                } else if trimmed.starts_with("~~~") {
+                    fence = Some("~~~");
+                }
+
+                if fence.is_some() {
+                    resolved.push_str(line);
+                    continue;
+                }
+            }
+        }
+
+        resolve_line(
+            line,
+            index,
+            prefix,
+            first_line + number,
+            &mut resolved,
+            &mut broken,
+        );
+    }
+
+    (resolved, broken)
+}
+
+fn resolve_line(
+    line: &str,
+    index: &ApiIndex,
+    prefix: &str,
+    line_number: usize,
+    resolved: &mut String,
+    broken: &mut Vec<BrokenLink>,
+) {
+    let bytes = line.as_bytes();
+
+    // everything before this has already been pushed onto `resolved`
+    let mut copied = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'`' => i = skip_code_span(line, i),
+            b'[' => match link_at(line, i) {
+                Some(link) => {
+                    match index.resolve(link.path) {
+                        Ok(url) => {
+                            resolved.push_str(&line[copied..i]);
+                            resolved.push_str(&format!("[{}]({}{})", link.text, prefix, url));
+                            copied = link.end;
+                        }
+                        // we can't check links to other crates, so those are left as they are
+                        Err(message) => broken.push(BrokenLink {
+                            line: line_number,
+                            message,
+                            explicit: link.explicit && index.is_local(link.path),
+                        }),
+                    }
+
+                    i = link.end;
+                }
+                None => i += 1,
+            },
+            _ => i += 1,
+        }
+    }
+
+    resolved.push_str(&line[copied..]);
+}
+
+/// Returns the index just past the code span that starts at `start`.
+fn skip_code_span(line: &str, start: usize) -> usize {
+    let ticks = line[start..].bytes().take_while(|&b| b == b'`').count();
+    let marker = &line[start..start + ticks];
+
+    let mut search = start + ticks;
+    while let Some(found) = line[search..].find(marker) {
+        let end = search + found;
+        let run = line[end..].bytes().take_while(|&b| b == b'`').count();
+
+        // the closing run has to be exactly as long as the opening one
+        if run == ticks {
+            return end + ticks;
+        }
+
+        search = end + run;
+    }
+
+    // an unclosed run of backticks is just backticks
+    start + ticks
+}
+
+/// An intra-doc link in a line of markdown.
+struct Link<'a> {
+    text: &'a str,
+    path: &'a str,
+    explicit: bool,
+
+    /// The index just past the end of the link
+    end: usize,
+}
+
+/// Read the intra-doc link that starts with the `[` at `start`, if there is one.
+fn link_at(line: &str, start: usize) -> Option<Link> {
+    let close = start + 1 + line[start + 1..].find(']')?;
+    let text = &line[start + 1..close];
+    let rest = &line[close + 1..];
+
+    if rest.starts_with('(') {
+        let destination_end = rest.find(')')?;
+        let destination = rest[1..destination_end].trim();
+
+        // a path with no `::` in it could just as well be a relative url
+        if !destination.contains("::") || !is_path(destination) {
+            return None;
+        }
+
+        return Some(Link {
+            text,
+            path: destination,
+            explicit: true,
+            end: close + 1 + destination_end + 1,
+        });
+    }
+
+    // reference links and their definitions aren't ours
+    if rest.starts_with('[') || rest.starts_with(':') {
+        return None;
+    }
+
+    let code = text.trim();
+    if code.len() < 2 || !code.starts_with('`') || !code.ends_with('`') {
+        return None;
+    }
+
+    let path = &code[1..code.len() - 1];
+    if !is_path(path) {
+        return None;
+    }
+
+    Some(Link {
+        text,
+        path,
+        explicit: false,
+        end: close + 1,
+    })
+}
+
+/// Is this a path to an item, like `examples::Point` or `::Config`?
+fn is_path(path: &str) -> bool {
+    let path = if path.starts_with("::") {
+        &path[2..]
+    } else {
+        path
+    };
+
+    path.split("::").all(|segment| {
+        let mut chars = segment.chars();
+
+        match chars.next() {
+            Some(first) if first == '_' || first.is_alphabetic() => {
+                chars.all(|c| c == '_' || c.is_alphanumeric())
+            }
+            _ => false,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_path, resolve_links, ApiIndex, BrokenLink};
+
+    fn index() -> ApiIndex {
+        let mut index = ApiIndex::default();
+
+        index.insert(
+            vec![String::from("examples")],
+            String::from("api/examples.html"),
+        );
+        index.insert(
+            vec![String::from("examples"), String::from("Point")],
+            String::from("api/examples/Point.html"),
+        );
+        index.insert(
+            vec![String::from("shapes"), String::from("Point")],
+            String::from("api/shapes/Point.html"),
+        );
+        index.insert(
+            vec![String::from("examples"), String::from("Speak")],
+            String::from("api/examples/Speak.html"),
+        );
+
+        index
+    }
+
+    #[test]
+    fn explicit_links() {
+        let (resolved, broken) = resolve_links(
+            "See [Point](::examples::Point) and [it](crate::examples::Point).",
+            &index(),
+            "../",
+            1,
+        );
+
+        assert!(broken.is_empty());
+        assert_eq!(
+            resolved,
+            "See [Point](../api/examples/Point.html) and [it](../api/examples/Point.html)."
+        );
+    }
+
+    #[test]
+    fn code_links() {
+        let (resolved, broken) = resolve_links("Use [`Speak`] to talk.", &index(), "/", 1);
+
+        assert!(broken.is_empty());
+        assert_eq!(resolved, "Use [`Speak`](/api/examples/Speak.html) to talk.");
+    }
+
+    #[test]
+    fn leaves_other_links_alone() {
+        let markdown = "[guide](guide.html) [ref][1] [plain] [`x y`]\n\n[1]: http://example.com";
+
+        let (resolved, broken) = resolve_links(markdown, &index(), "/", 1);
+
+        assert!(broken.is_empty());
+        assert_eq!(resolved, markdown);
+    }
+
+    #[test]
+    fn leaves_code_alone() {
+        let markdown = "`[Point](::examples::Point)`\n\n```\n[`Point`]\n```\n";
+
+        let (resolved, broken) = resolve_links(markdown, &index(), "/", 1);
+
+        assert!(broken.is_empty());
+        assert_eq!(resolved, markdown);
+    }
+
+    #[test]
+    fn broken_links() {
+        let markdown = "# Guide\n\n[Pointt](::examples::Pointt)\n[`Point`]\n[`u8`]";
+
+        let (_, broken) = resolve_links(markdown, &index(), "/", 5);
+
+        assert_eq!(
+            broken,
+            vec![
+                BrokenLink {
+                    line: 7,
+                    message: String::from("no API item is named `examples::Pointt`"),
+                    explicit: true,
+                },
+                BrokenLink {
+                    line: 8,
+                    message: String::from(
+                        "`Point` could be any of `examples::Point`, `shapes::Point`; use more of its path"
+                    ),
+                    explicit: false,
+                },
+                BrokenLink {
+                    line: 9,
+                    message: String::from("no API item is named `u8`"),
+                    explicit: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn links_to_other_crates_are_left_alone() {
+        let mut index = index();
+        index.add_crate(String::from("example"), Vec::new());
+
+        let markdown = "[x](::std::fmt::Display) [y](example::Nope) [z](crate::Nope)";
+
+        let (resolved, broken) = resolve_links(markdown, &index, "/", 1);
+
+        assert_eq!(resolved, markdown);
+
+        let explicit: Vec<_> = broken.iter().map(|link| link.explicit).collect();
+        assert_eq!(explicit, vec![false, true, true]);
+    }
+
+    #[test]
+    fn paths() {
+        assert!(is_path("Point"));
+        assert!(is_path("::examples::Point"));
+        assert!(is_path("_private"));
+        assert!(!is_path("guide.html"));
+        assert!(!is_path("examples::"));
+        assert!(!is_path("1st"));
+        assert!(!is_path(""));
+    }
+
+    #[test]
+    fn links_that_start_with_the_crate_name() {
+        let mut index = index();
+        index.add_crate(String::from("example"), Vec::new());
+        index.insert(Vec::new(), String::from("api/index.html"));
+
+        let (resolved, broken) = resolve_links(
+            "[X](example::examples::Point) [Y](::example::Speak) [Z](::example)",
+            &index,
+            "/",
+            1,
+        );
+
+        assert!(broken.is_empty(), "{:?}", broken);
+        assert_eq!(
+            resolved,
+            "[X](/api/examples/Point.html) [Y](/api/examples/Speak.html) [Z](/api/index.html)"
+        );
+    }
+
+    #[test]
+    fn links_into_one_of_several_crates() {
+        let mut index = ApiIndex::default();
+        index.add_crate(String::from("my_crate"), vec![String::from("my-crate")]);
+        index.add_crate(String::from("my_cli"), vec![String::from("my-cli")]);
+
+        index.insert(
+            vec![String::from("my-crate"), String::from("Config")],
+            String::from("api/my-crate/Config.html"),
+        );
+        index.insert(
+            vec![String::from("my-cli"), String::from("Config")],
+            String::from("api/my-cli/Config.html"),
+        );
+
+        let (resolved, broken) =
+            resolve_links("[X](my_crate::Config) [Y](my_cli::Config)", &index, "/", 1);
+
+        assert!(broken.is_empty(), "{:?}", broken);
+        assert_eq!(
+            resolved,
+            "[X](/api/my-crate/Config.html) [Y](/api/my-cli/Config.html)"
+        );
+    }
+}
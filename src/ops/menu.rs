@@ -0,0 +1,509 @@
+//! Reading, checking and adding to `Menu.toml`.
+//!
+//! Each key in `Menu.toml` is the title of a section of the sidebar, and its value is an array of
+//! the ids of the pages in that section, in order.
+
+use toml;
+use toml_edit;
+
+use std::collections::HashSet;
+
+/// A section of the menu.
+#[derive(Debug, PartialEq)]
+pub struct Section {
+    pub title: String,
+    pub ids: Vec<String>,
+}
+
+/// Something wrong with `Menu.toml`: the line it's on, if we know it, and what's wrong.
+pub type Problem = (Option<usize>, String);
+
+/// The result of checking `Menu.toml` against the pages that exist.
+#[derive(Debug, Default)]
+pub struct Checked {
+    /// The sections, with only the ids that refer to real pages.
+    pub sections: Vec<Section>,
+
+    /// Problems that mean the menu can't be built.
+    pub errors: Vec<Problem>,
+
+    /// Ids of pages that exist, but aren't in any section.
+    pub unlisted: Vec<String>,
+}
+
+/// Parse `Menu.toml` and check it against the ids of every page that exists.
+pub fn check(contents: &str, page_ids: &[&str]) -> Checked {
+    let mut checked = Checked::default();
+
+    let doc = match contents.parse::<toml_edit::Document>() {
+        Ok(doc) => doc,
+        Err(e) => {
+            checked.errors.push((error_line(contents), format!("invalid toml: {}", e)));
+            return checked;
+        }
+    };
+
+    let mut seen = HashSet::new();
+
+    for (title, body) in doc.iter() {
+        let section_line = section_line(contents, title);
+
+        let body = match body.as_value().and_then(|value| value.as_array()) {
+            Some(body) => body,
+            None => {
+                checked.errors.push((
+                    section_line,
+                    format!("section `{}` should be an array of page ids", title),
+                ));
+                continue;
+            }
+        };
+
+        let mut ids = Vec::new();
+
+        // ids are found in order, so we keep track of where the last one was
+        let mut cursor = section_line.unwrap_or(1);
+
+        for value in body.iter() {
+            let id = match value.as_str() {
+                Some(id) => id,
+                None => {
+                    checked.errors.push((
+                        section_line,
+                        format!("section `{}` should only contain strings", title),
+                    ));
+                    continue;
+                }
+            };
+
+            let line = id_line(contents, id, cursor);
+            if let Some(line) = line {
+                cursor = line;
+            }
+
+            if !page_ids.contains(&id) {
+                let message = match suggest(id, page_ids) {
+                    Some(suggestion) => format!(
+                        "no page has the id `{}`; did you mean `{}`?",
+                        id, suggestion
+                    ),
+                    None => format!("no page has the id `{}`", id),
+                };
+
+                checked.errors.push((line, message));
+                continue;
+            }
+
+            if !seen.insert(id.to_string()) {
+                checked
+                    .errors
+                    .push((line, format!("`{}` appears in the menu more than once", id)));
+                continue;
+            }
+
+            ids.push(id.to_string());
+        }
+
+        checked.sections.push(Section {
+            title: title.to_string(),
+            ids,
+        });
+    }
+
+    checked.unlisted = page_ids
+        .iter()
+        .filter(|id| !seen.contains(**id))
+        .map(|id| id.to_string())
+        .collect();
+
+    checked
+}
+
+/// Write out a whole section of the menu, the way `init` lays it out.
+pub fn section(title: &str, ids: &[String]) -> String {
+    let mut section = format!("{} = [\n", toml::Value::String(title.to_string()));
+
+    for id in ids {
+        section.push_str(&format!("    {},\n", toml::Value::String(id.clone())));
+    }
+
+    section.push_str("]\n");
+    section
+}
+
+/// Add ids to the end of a section of `Menu.toml`, adding the section if it isn't there.
+///
+/// The rest of the file is left exactly as it was, comments and all. Returns what's wrong with
+/// the menu, if the ids can't be added.
+pub fn add_to_section(
+    contents: &str,
+    title: &str,
+    ids: &[String],
+) -> ::std::result::Result<String, String> {
+    let doc = contents
+        .parse::<toml_edit::Document>()
+        .map_err(|e| format!("invalid toml: {}", e))?;
+
+    if ids.is_empty() {
+        return Ok(contents.to_string());
+    }
+
+    if doc.iter().all(|(key, _)| key != title) {
+        let mut contents = contents.trim_right().to_string();
+
+        if !contents.is_empty() {
+            contents.push_str("\n\n");
+        }
+
+        contents.push_str(&section(title, ids));
+        return Ok(contents);
+    }
+
+    let body = doc[title]
+        .as_value()
+        .and_then(|value| value.as_array())
+        .ok_or_else(|| format!("section `{}` should be an array of page ids", title))?;
+
+    if body.iter().any(|value| value.as_str().is_none()) {
+        return Err(format!("section `{}` should only contain strings", title));
+    }
+
+    // toml_edit would put new ids on the same line as the last one, so we add them ourselves
+    section_line(contents, title)
+        .and_then(|line| append_to_array(contents, line, ids))
+        .ok_or_else(|| format!("could not find the end of section `{}`", title))
+}
+
+/// Add ids to the end of the array that starts on `line`, one to a line if the array is already
+/// split over several, the way `section` lays them out.
+fn append_to_array(contents: &str, line: usize, ids: &[String]) -> Option<String> {
+    let start: usize = contents
+        .split('\n')
+        .take(line - 1)
+        .map(|line| line.len() + 1)
+        .sum();
+
+    let mut chars = contents[start..]
+        .char_indices()
+        .map(|(index, c)| (start + index, c));
+
+    let mut open = None;
+    let mut close = None;
+
+    // where the last id ends, and whether there's a comma after it
+    let mut last = None;
+    let mut comma = false;
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' | '\'' => {
+                let quote = c;
+                let mut end = None;
+                let mut escaped = false;
+
+                for (index, c) in &mut chars {
+                    if escaped {
+                        escaped = false;
+                    } else if c == '\\' && quote == '"' {
+                        escaped = true;
+                    } else if c == quote {
+                        end = Some(index + 1);
+                        break;
+                    }
+                }
+
+                // the section's title is a string too
+                if open.is_some() {
+                    last = end;
+                    comma = false;
+                }
+            }
+            '#' => {
+                for (_, c) in &mut chars {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '[' if open.is_none() => open = Some(index),
+            ',' if last.is_some() => {
+                last = Some(index + 1);
+                comma = true;
+            }
+            ']' if open.is_some() => {
+                close = Some(index);
+                break;
+            }
+            _ => (),
+        }
+    }
+
+    let (open, close) = (open?, close?);
+
+    let entries: Vec<_> = ids
+        .iter()
+        .map(|id| toml::Value::String(id.clone()).to_string())
+        .collect();
+
+    let split = contents[open..close].contains('\n');
+
+    let added: String = match (split, last.is_some(), comma) {
+        (true, true, false) => entries.iter().map(|id| format!(",\n    {}", id)).collect(),
+        (true, _, _) => entries.iter().map(|id| format!("\n    {},", id)).collect(),
+        (false, true, false) => entries.iter().map(|id| format!(", {}", id)).collect(),
+        (false, true, true) => entries.iter().map(|id| format!(" {},", id)).collect(),
+        (false, false, _) => entries.join(", "),
+    };
+
+    let at = last.unwrap_or(open + 1);
+
+    Some(format!("{}{}{}", &contents[..at], added, &contents[at..]))
+}
+
+/// Find the line a toml syntax error is on, starting at one.
+fn error_line(contents: &str) -> Option<usize> {
+    // toml_edit only puts the line in its message, but toml can tell us what it is
+    toml::from_str::<toml::Value>(contents)
+        .err()
+        .and_then(|e| e.line_col())
+        .map(|(line, _)| line + 1)
+}
+
+/// Find the line a section is defined on, starting at one.
+fn section_line(contents: &str, title: &str) -> Option<usize> {
+    let quoted = format!("\"{}\"", title);
+    let literal = format!("'{}'", title);
+
+    contents
+        .lines()
+        .position(|line| {
+            let line = line.trim_left();
+            line.starts_with(&quoted) || line.starts_with(&literal)
+                || line.starts_with(&format!("{} ", title))
+                || line.starts_with(&format!("{}=", title))
+        })
+        .map(|index| index + 1)
+}
+
+/// Find the line an id appears on, starting the search at line `from`.
+fn id_line(contents: &str, id: &str, from: usize) -> Option<usize> {
+    let quoted = format!("\"{}\"", id);
+    let literal = format!("'{}'", id);
+
+    contents
+        .lines()
+        .enumerate()
+        .skip(from.saturating_sub(1))
+        .find(|&(_, line)| line.contains(&quoted) || line.contains(&literal))
+        .map(|(index, _)| index + 1)
+}
+
+/// Find the page id that's closest to `id`, if any is close enough to be a likely typo.
+fn suggest<'a>(id: &str, page_ids: &[&'a str]) -> Option<&'a str> {
+    let threshold = ::std::cmp::max(1, id.chars().count() / 3);
+
+    page_ids
+        .iter()
+        .map(|candidate| (edit_distance(id, candidate), *candidate))
+        .filter(|&(distance, _)| distance <= threshold)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+
+    // we only need the previous row of the table to compute the next one
+    let mut previous: Vec<usize> = (0..b.len() + 1).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + if a_char == *b_char { 0 } else { 1 };
+            let deletion = previous[j + 1] + 1;
+            let insertion = current[j] + 1;
+
+            current.push(*[substitution, deletion, insertion].iter().min().unwrap());
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_to_section, check, edit_distance, Section};
+
+    const MENU: &str = r#""Getting Started" = [
+    "overview",
+    "quickstart",
+]
+
+"Examples" = [
+    "simple-cli.rs",
+]"#;
+
+    #[test]
+    fn valid_menu() {
+        let checked = check(MENU, &["overview", "quickstart", "simple-cli.rs"]);
+
+        assert!(checked.errors.is_empty());
+        assert!(checked.unlisted.is_empty());
+        assert_eq!(
+            checked.sections,
+            vec![
+                Section {
+                    title: String::from("Getting Started"),
+                    ids: vec![String::from("overview"), String::from("quickstart")],
+                },
+                Section {
+                    title: String::from("Examples"),
+                    ids: vec![String::from("simple-cli.rs")],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_id_with_suggestion() {
+        let checked = check(MENU, &["overview", "quick-start", "simple-cli.rs"]);
+
+        assert_eq!(
+            checked.errors,
+            vec![(
+                Some(3),
+                String::from("no page has the id `quickstart`; did you mean `quick-start`?"),
+            )]
+        );
+        assert_eq!(checked.unlisted, vec![String::from("quick-start")]);
+    }
+
+    #[test]
+    fn unknown_id_without_suggestion() {
+        let checked = check(MENU, &["overview", "simple-cli.rs"]);
+
+        assert_eq!(
+            checked.errors,
+            vec![(Some(3), String::from("no page has the id `quickstart`"))]
+        );
+    }
+
+    #[test]
+    fn duplicate_ids() {
+        let menu = r#""Getting Started" = [
+    "overview",
+]
+
+"Again" = [
+    "overview",
+]"#;
+
+        let checked = check(menu, &["overview"]);
+
+        assert_eq!(
+            checked.errors,
+            vec![(
+                Some(6),
+                String::from("`overview` appears in the menu more than once"),
+            )]
+        );
+    }
+
+    #[test]
+    fn type_errors() {
+        let menu = r#""Getting Started" = "overview"
+
+"Numbers" = [
+    5,
+]"#;
+
+        let checked = check(menu, &["overview"]);
+
+        assert_eq!(
+            checked.errors,
+            vec![
+                (
+                    Some(1),
+                    String::from("section `Getting Started` should be an array of page ids"),
+                ),
+                (
+                    Some(3),
+                    String::from("section `Numbers` should only contain strings"),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_toml() {
+        let checked = check("\"Getting Started\" = [\n    \"overview\",\n    oops\n]", &[]);
+
+        assert_eq!(checked.errors.len(), 1);
+        assert_eq!(checked.errors[0].0, Some(3));
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("overview", "overview"), 0);
+        assert_eq!(edit_distance("overveiw", "overview"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("quickstart", "quick-start"), 1);
+    }
+
+    #[test]
+    fn adds_to_an_existing_section() {
+        let added = add_to_section(MENU, "Examples", &[String::from("new-thing.rs")]).unwrap();
+
+        assert_eq!(
+            added,
+            MENU.replace("\"simple-cli.rs\",\n", "\"simple-cli.rs\",\n    \"new-thing.rs\",\n")
+        );
+
+        let checked = check(
+            &added,
+            &["overview", "quickstart", "simple-cli.rs", "new-thing.rs"],
+        );
+
+        assert!(checked.errors.is_empty(), "{:?}", checked.errors);
+        assert_eq!(
+            checked.sections[1].ids,
+            vec![String::from("simple-cli.rs"), String::from("new-thing.rs")]
+        );
+    }
+
+    #[test]
+    fn keeps_the_layout_of_a_section() {
+        let new = [String::from("c"), String::from("d")];
+        let add = |menu: &str| add_to_section(menu, "Examples", &new).unwrap();
+
+        assert_eq!(
+            add("\"Examples\" = [\n    \"a\",\n    \"b\" # the end\n]\n"),
+            "\"Examples\" = [\n    \"a\",\n    \"b\",\n    \"c\",\n    \"d\" # the end\n]\n"
+        );
+        assert_eq!(
+            add("Examples = [\"a\", 'b'] # [x]\n"),
+            "Examples = [\"a\", 'b', \"c\", \"d\"] # [x]\n"
+        );
+        assert_eq!(add("Examples = []"), "Examples = [\"c\", \"d\"]");
+        assert_eq!(
+            add("# ]\nExamples = [\n]"),
+            "# ]\nExamples = [\n    \"c\",\n    \"d\",\n]"
+        );
+    }
+
+    #[test]
+    fn adds_a_missing_section() {
+        let menu = "\"Getting Started\" = [\n    \"overview\",\n]\n";
+
+        let added = add_to_section(menu, "Examples", &[String::from("simple-cli.rs")]).unwrap();
+
+        assert_eq!(
+            added,
+            "\"Getting Started\" = [\n    \"overview\",\n]\n\n\"Examples\" = [\n    \"simple-cli.rs\",\n]\n"
+        );
+    }
+}
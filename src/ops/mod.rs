@@ -1,13 +1,25 @@
+mod api_diff;
 mod build;
+mod cache;
+mod check;
 mod clean;
+mod coverage;
+mod diff;
+mod generated;
 mod init;
+mod intra_doc;
+mod menu;
 mod publish;
+mod search;
 mod serve;
 mod update;
 
+pub use self::api_diff::{api_diff, ApiDiffOptions};
 pub use self::build::build;
+pub use self::check::check;
 pub use self::clean::clean;
+pub use self::coverage::{coverage, CoverageOptions};
 pub use self::init::init;
 pub use self::publish::publish;
 pub use self::serve::serve;
-pub use self::update::update;
+pub use self::update::{update, UpdateOptions};
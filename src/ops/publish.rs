@@ -1,9 +1,5 @@
-use toml_edit;
 use slog::Logger;
 
-use std::fs::File;
-use std::io::prelude::*;
-
 use config::Config;
 use git;
 use Result;
@@ -13,26 +9,12 @@ pub fn publish(config: &Config, log: &Logger) -> Result<()> {
     let log = log.new(o!("command" => "publish"));
     info!(log, "starting");
 
-    // load up our Doxidize.toml so we can handle any base urls
-    let path = config.config_path();
-    let mut contents = String::new();
-    let mut toml_file = File::open(path)?;
-    toml_file.read_to_string(&mut contents)?;
-
-    let doc = contents
-        .parse::<toml_edit::Document>()
-        .expect("invalid doxidize.toml");
-
-    let base_url = doc["docs"]["base-url"]
-        .as_value()
-        .map(|v| v.as_str().expect("value of base-url was not a string"))
-        .unwrap_or_default()
-        .to_string();
-
-    let mut target_dir = config.output_path().join("public");
+    // this is the same directory that build writes to, so `clean` deletes the repository along
+    // with everything else, and it's set up again below
+    let mut target_dir = config.output_path();
 
-    if !base_url.is_empty() {
-        target_dir.push(&base_url)
+    if !config.base_url().is_empty() {
+        target_dir.push(config.base_url())
     }
 
     let git_dir = target_dir.join(".git");
@@ -53,7 +35,10 @@ pub fn publish(config: &Config, log: &Logger) -> Result<()> {
     // to set it up we need to initialize the git repository, add the remote, and sync the two
     if !pages_head.is_file() {
         git::init(&target_dir)?;
-        git::initialize_remote(&target_dir, remote_name, !base_url.is_empty())?;
+        // the output directory can be anywhere, and the crate can be anywhere in the project's
+        // repo, so we ask git where that is
+        let project_repo = git::show_toplevel(config.root_path())?;
+        git::initialize_remote(&target_dir, remote_name, &project_repo)?;
         git::reset_to_remote_head(&target_dir, remote_name)?;
     }
 
@@ -0,0 +1,236 @@
+//! Building the index used by the search box on every page.
+//!
+//! The index is written as a script that sets `window.searchIndex`, rather than as plain JSON,
+//! so that pages can load it with a `<script>` tag. That works wherever the docs are viewed from,
+//! including straight off the filesystem, where fetching JSON isn't allowed.
+
+use serde_json;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use Result;
+
+/// A page in the search index.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Entry {
+    pub title: String,
+
+    /// The kind of API item the page documents, like `struct` or `module`, if it documents one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+
+    /// The url of the page, relative to the root of the site
+    pub url: String,
+
+    pub headings: Vec<String>,
+    pub text: String,
+}
+
+impl Entry {
+    /// Create the entry for a page from its markdown and the html it was rendered to.
+    ///
+    /// API pages don't have a title in their front matter, so pass an empty `title` for those,
+    /// and the item's name is used instead.
+    pub fn new(title: &str, url: String, markdown: &str, html: &str) -> Entry {
+        let headings = headings(html);
+        let item = if title.is_empty() {
+            api_item(markdown)
+        } else {
+            None
+        };
+
+        let title = if !title.is_empty() {
+            title.to_string()
+        } else if let Some((_, ref name)) = item {
+            name.clone()
+        } else {
+            headings.first().cloned().unwrap_or_default()
+        };
+
+        Entry {
+            title,
+            kind: item.map(|(kind, _)| kind),
+            url,
+            headings,
+            text: strip_tags(html),
+        }
+    }
+}
+
+/// Write the index to `path`.
+pub fn write(path: &Path, entries: &[&Entry]) -> Result<()> {
+    let json = serde_json::to_string(entries)?;
+
+    let mut file = File::create(path)?;
+    file.write_all(format!("window.searchIndex = {};\n", json).as_bytes())?;
+
+    Ok(())
+}
+
+/// Find the kind and name of the item an API page documents, from a first line like
+/// ``# struct `Point` ``. The root of each crate has a first line like ``# `my_crate` ``.
+///
+/// Comments, like the ones that mark where the generated markdown starts, are skipped.
+pub fn api_item(markdown: &str) -> Option<(String, String)> {
+    let heading = markdown
+        .lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !line.starts_with("<!--"))?;
+
+    if !heading.starts_with("# ") || !heading.ends_with('`') {
+        return None;
+    }
+
+    let heading = heading[2..].trim_left();
+    let name_start = heading.find('`')?;
+
+    if name_start + 1 >= heading.len() {
+        return None;
+    }
+
+    let kind = heading[..name_start].trim();
+    let name = heading[name_start + 1..heading.len() - 1].to_string();
+
+    if name.is_empty() || name.contains('`') {
+        return None;
+    }
+
+    let kind = if kind.is_empty() { "crate" } else { kind };
+
+    Some((kind.to_string(), name))
+}
+
+/// Collect the text of every heading in some html.
+fn headings(html: &str) -> Vec<String> {
+    let mut headings = Vec::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find("<h") {
+        let after = &rest[start + 2..];
+
+        // skip other tags that start with `h`, like `<hr />`
+        match after.chars().next() {
+            Some(level) if level >= '1' && level <= '6' => (),
+            _ => {
+                rest = after;
+                continue;
+            }
+        }
+
+        let (open_end, close) = match (after.find('>'), after.find("</h")) {
+            (Some(open_end), Some(close)) => (open_end + 1, close),
+            _ => break,
+        };
+
+        if open_end <= close {
+            headings.push(strip_tags(&after[open_end..close]));
+        }
+
+        rest = &after[close..];
+    }
+
+    headings
+}
+
+/// Turn html into plain text, with all of the whitespace collapsed.
+fn strip_tags(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+
+    for c in html.chars() {
+        match c {
+            '<' => {
+                // tags usually separate words, so we keep something in their place
+                in_tag = true;
+                text.push(' ');
+            }
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => (),
+        }
+    }
+
+    let text = text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{api_item, headings, strip_tags, Entry};
+
+    #[test]
+    fn guide_page() {
+        let entry = Entry::new(
+            "Guide",
+            String::from("guide.html"),
+            "# Guide\n\nSome text.\n\n## Details\n",
+            "<h1>Guide</h1>\n<p>Some text.</p>\n<h2>Details</h2>\n",
+        );
+
+        assert_eq!(
+            entry,
+            Entry {
+                title: String::from("Guide"),
+                kind: None,
+                url: String::from("guide.html"),
+                headings: vec![String::from("Guide"), String::from("Details")],
+                text: String::from("Guide Some text. Details"),
+            }
+        );
+    }
+
+    #[test]
+    fn api_page() {
+        let entry = Entry::new(
+            "",
+            String::from("api/Point.html"),
+            "# struct `Point`\n\nA point.\n",
+            "<h1>struct <code>Point</code></h1>\n<p>A point.</p>\n",
+        );
+
+        assert_eq!(entry.title, "Point");
+        assert_eq!(entry.kind, Some(String::from("struct")));
+    }
+
+    #[test]
+    fn api_items() {
+        assert_eq!(
+            api_item("# module `examples`\n"),
+            Some((String::from("module"), String::from("examples")))
+        );
+        assert_eq!(
+            api_item("# `doxidize`\n"),
+            Some((String::from("crate"), String::from("doxidize")))
+        );
+        assert_eq!(
+            api_item("<!-- doxidize:generated 0 -->\n# struct `Point`\n"),
+            Some((String::from("struct"), String::from("Point")))
+        );
+        assert_eq!(api_item("# Overview\n"), None);
+        assert_eq!(api_item("Some `code`\n"), None);
+        assert_eq!(api_item("# `\n"), None);
+    }
+
+    #[test]
+    fn collects_headings() {
+        assert_eq!(
+            headings("<h1>One</h1><hr /><p>text</p><h3>Two <em>three</em></h3>"),
+            vec![String::from("One"), String::from("Two three")]
+        );
+    }
+
+    #[test]
+    fn strips_tags() {
+        assert_eq!(
+            strip_tags("<p>Tom &amp; Jerry</p>\n<p>&lt;T&gt;</p>"),
+            "Tom & Jerry <T>"
+        );
+    }
+}
@@ -1,38 +1,147 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::prelude::*;
+use std::net::TcpListener;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
 use ctrlc;
-use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
 use simple_server::Server;
 use slog::Logger;
+use walkdir::WalkDir;
 
+use cargo;
 use config::Config;
-use ops;
+use ops::{self, cache, UpdateOptions};
 use Result;
 
-static SERVER_ADDR: (&str, &str) = ("127.0.0.1", "7878");
+/// How long the sources have to be left alone before the API docs are regenerated.
+const SOURCE_QUIET_PERIOD_MS: u64 = 500;
+
+/// How many ports to try, starting with the configured one, before giving up.
+const PORT_ATTEMPTS: u16 = 100;
+
+/// The live reload client asks this for the state of the latest build.
+static STATUS_PATH: &str = "/__doxidize/status";
+
+/// The live reload client, which is added to every page we serve.
+static LIVE_RELOAD: &str = include_str!("../../templates/html/live-reload.html");
 
 /// Requests sent internally to the serve function from other threads.
 enum Request {
-    /// Trigger a rebuild.
-    Build,
+    /// Trigger a rebuild, because of a change to these files. If we don't know which files
+    /// changed, there are none.
+    Build(Vec<PathBuf>),
+
+    /// Regenerate the API docs, and then rebuild.
+    Update,
 
     /// Terminate the server.
     Terminate,
+
+    /// The server couldn't listen, or stopped listening, for this reason.
+    ServerStopped(String),
+}
+
+/// The contents of the markdown files as they were when the docs were last built, so that events
+/// about files that haven't changed since don't cause another build.
+#[derive(Debug, Default)]
+struct Snapshot {
+    /// The hash of each file's contents
+    hashes: HashMap<PathBuf, u64>,
+}
+
+impl Snapshot {
+    /// Hash every file in `dir`.
+    fn take(dir: &Path) -> Snapshot {
+        let hashes = WalkDir::new(dir)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| {
+                let hash = hash_file(entry.path())?;
+                Some((entry.path().to_path_buf(), hash))
+            })
+            .collect();
+
+        Snapshot { hashes }
+    }
+
+    /// Whether any of these files may have changed since the snapshot was taken.
+    ///
+    /// Directories may have had anything happen to the files in them, and so may anything, if we
+    /// don't know which files changed at all.
+    fn changed(&self, paths: &[PathBuf]) -> bool {
+        paths.is_empty() || paths.iter().any(|path| {
+            if path.is_dir() {
+                return true;
+            }
+
+            match hash_file(path) {
+                Some(hash) => self.hashes.get(path) != Some(&hash),
+                // only something that was built from can be missing from the build now
+                None => self.hashes.keys().any(|built| built.starts_with(path)),
+            }
+        })
+    }
+}
+
+/// Hash the contents of a file, if it can be read.
+fn hash_file(path: &Path) -> Option<u64> {
+    let mut contents = Vec::new();
+
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut contents))
+        .ok()?;
+
+    Some(cache::hash(&contents))
+}
+
+/// What the live reload client needs to know about the latest build.
+#[derive(Default)]
+struct BuildStatus {
+    /// Goes up by one every time the docs are rebuilt, so pages can tell that they're stale
+    build: u64,
+
+    /// Why the last rebuild failed, if it did
+    error: Option<String>,
 }
 
 /// Serve documentation locally with an HTTP server.
 ///
+/// The docs are built before the server starts, so that what's served is up to date. If they
+/// can't be built, we don't start at all.
+///
 /// In addition, this function registers a file watcher on the markdown source folder and triggers
-/// a rebuild of documentation when any of those files change.
+/// a rebuild of documentation when any of those files change. With `watch_src`, it also watches
+/// the sources of the documented targets and the examples, and regenerates the API docs before
+/// rebuilding when they change.
+///
+/// Every page that's served has a small script added to it, which reloads the page after each
+/// rebuild, and shows the error when a rebuild fails.
 ///
 /// Lastly, it registers a Ctrl-C handler to cleanly exit when the user requests shutdown.
 /// Otherwise, the server continues indefinitely.
-pub fn serve(config: &Config, log: &Logger) -> Result<()> {
+pub fn serve(config: &Config, log: &Logger, watch_src: bool) -> Result<()> {
     let log = log.new(o!("command" => "serve"));
     info!(log, "starting");
 
+    // changes to files from before this were part of the last build, so they don't need another
+    let mut built = Snapshot::take(&config.markdown_path());
+
+    info!(log, "building docs");
+    if let Err(e) = ops::build(config, &log) {
+        bail!(
+            "the docs could not be built, so there's nothing to serve. Fix this and try again:\n{}",
+            e
+        );
+    }
+
     let watcher_log = log.new(o!("step" => "watching"));
     let (watcher_tx, watcher_rx) = channel();
 
@@ -48,28 +157,138 @@ pub fn serve(config: &Config, log: &Logger) -> Result<()> {
     thread::spawn(move || {
         for event in watcher_rx {
             trace!(watcher_log, "received event from watcher: {:?}", event);
-            watcher_request_tx.send(Request::Build).unwrap();
+            watcher_request_tx.send(Request::Build(event_paths(event))).unwrap();
         }
     });
 
+    // this has to live as long as we're serving, or it'll stop watching
+    let _source_watcher = if watch_src {
+        let source_watcher_log = log.new(o!("step" => "watching sources"));
+        let (source_watcher_tx, source_watcher_rx) = channel();
+
+        let mut source_watcher: RecommendedWatcher =
+            Watcher::new(source_watcher_tx, Duration::from_secs(2))?;
+
+        for path in &source_dirs(config)? {
+            if path.is_dir() {
+                info!(log, "watching {} for changes", path.display());
+                source_watcher.watch(path, RecursiveMode::Recursive)?;
+            }
+        }
+
+        let source_request_tx = request_tx.clone();
+        thread::spawn(move || {
+            let quiet_period = Duration::from_millis(SOURCE_QUIET_PERIOD_MS);
+
+            while let Ok(event) = source_watcher_rx.recv() {
+                trace!(source_watcher_log, "received event from watcher: {:?}", event);
+
+                // saving several files at once, or running a formatter, makes a burst of events,
+                // and regenerating the API docs once is enough for all of them
+                while let Ok(event) = source_watcher_rx.recv_timeout(quiet_period) {
+                    trace!(source_watcher_log, "received event from watcher: {:?}", event);
+                }
+
+                source_request_tx.send(Request::Update).unwrap();
+            }
+        });
+
+        Some(source_watcher)
+    } else {
+        None
+    };
+
     // Register a Ctrl-C handler for nice cleanup once the server is started.
     let ctrlc_request_tx = request_tx.clone();
     ctrlc::set_handler(move || {
         ctrlc_request_tx.send(Request::Terminate).unwrap();
     })?;
 
-    // everything is handled by the static serving, so any request here is an error
-    let mut server =
-        Server::new(|_request, mut response| Ok(response.body(b"incorrect path".to_vec())?));
+    let status = Arc::new(Mutex::new(BuildStatus::default()));
+
+    // like on GitHub Pages, only what's under the base url is served
+    let mut target_dir = config.output_path();
+    if !config.base_url().is_empty() {
+        target_dir.push(config.base_url());
+    }
+
+    trace!(log, "serving files from"; o!("dir" => target_dir.display()));
+
+    let base_url = config.base_url().to_string();
+
+    // we serve the files ourselves, rather than with the static serving, so that the live
+    // reload client can be added to each page
+    let server_status = status.clone();
+    let server = Server::new(move |request, mut response| {
+        let url_path = request.uri().path();
+
+        if url_path == STATUS_PATH {
+            let status = server_status.lock().unwrap();
+            let body = json!({ "build": status.build, "error": status.error }).to_string();
+
+            return Ok(response
+                .header("Content-Type", "application/json")
+                .header("Cache-Control", "no-store")
+                .body(body.into_bytes())?);
+        }
+
+        // the page may be on its way, so even the not found page reloads after a rebuild
+        let build = server_status.lock().unwrap().build;
+
+        let file = match route(&target_dir, &base_url, url_path) {
+            Route::File(file) => file,
+            Route::Redirect(location) => {
+                return Ok(response
+                    .status(301)
+                    .header("Location", &location[..])
+                    .body(Vec::new())?);
+            }
+            Route::NotFound => {
+                response.status(404);
+
+                let not_found = target_dir.join("404.html");
+                if !not_found.is_file() {
+                    let html = not_found_page(url_path, &base_url);
 
-    let path = config.output_path();
+                    return Ok(response
+                        .header("Content-Type", "text/html; charset=utf-8")
+                        .body(add_live_reload(&html, build).into_bytes())?);
+                }
+
+                not_found
+            }
+        };
 
-    trace!(log, "setting static directory"; o!("dir" => path.display()));
-    server.set_static_directory(path);
+        let mut contents = Vec::new();
+        File::open(&file)?.read_to_end(&mut contents)?;
+
+        if file.extension() == Some("html".as_ref()) {
+            let html = add_live_reload(&String::from_utf8_lossy(&contents), build);
+            contents = html.into_bytes();
+        }
+
+        Ok(response
+            .header("Content-Type", content_type(&file))
+            .body(contents)?)
+    });
 
     let log = log.new(o!("step" => "starting server"));
 
-    let (host, port) = SERVER_ADDR;
+    let host = config.serve_host().to_string();
+    let port = match free_port(&host, config.serve_port()) {
+        Some(port) => port,
+        None => bail!(
+            "could not listen on {}: ports {} to {} are all unavailable",
+            host,
+            config.serve_port(),
+            config.serve_port().saturating_add(PORT_ATTEMPTS - 1)
+        ),
+    };
+
+    if port != config.serve_port() {
+        warn!(log, "port {} is in use, so using {} instead", config.serve_port(), port);
+    }
+
     if config.base_url().is_empty() {
         info!(log, "serving docs at http://{}:{}/index.html", host, port);
     } else {
@@ -85,28 +304,318 @@ pub fn serve(config: &Config, log: &Logger) -> Result<()> {
     // Start the server. This thread will never return, so we just expect it to be cleaned up on
     // program exit.
     let server_log = log.clone();
+    let server_request_tx = request_tx.clone();
+    let address = format!("{}:{}", host, port);
     thread::spawn(move || {
         info!(server_log, "press Ctrl+C to quit");
-        server.listen(host, port);
+
+        // the port was free when we looked, but something else may have taken it since, and then
+        // listening panics
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            server.listen(&host, &port.to_string());
+        }));
+
+        let reason = match result {
+            Ok(()) => String::from("the server stopped"),
+            Err(payload) => payload
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| payload.downcast_ref::<&str>().map(|reason| reason.to_string()))
+                .unwrap_or_else(|| String::from("the server panicked")),
+        };
+
+        server_request_tx.send(Request::ServerStopped(reason)).unwrap();
     });
 
     for request in request_rx {
-        match request {
-            Request::Build => {
+        let result = match request {
+            Request::Build(ref paths) if !built.changed(paths) => {
+                // most likely the API docs we wrote ourselves after a source change
+                trace!(log, "files were already built, skipping";
+                o!("files" => format!("{:?}", paths)));
+                continue;
+            }
+            Request::Build(_) => {
                 info!(log, "file changed, regenerating docs");
 
-                if let Err(e) = ops::build(config, &log) {
-                    error!(log, "error building: {:?}", e);
-                }
+                built = Snapshot::take(&config.markdown_path());
+                ops::build(config, &log)
+            }
+            Request::Update => {
+                info!(log, "source changed, regenerating api docs");
 
-                info!(log, "done");
+                let options = UpdateOptions::default();
+
+                ops::update(config, &log, &options).and_then(|()| {
+                    built = Snapshot::take(&config.markdown_path());
+                    ops::build(config, &log)
+                })
             }
             Request::Terminate => {
                 info!(log, "termination signal received, shutting down");
                 break;
             }
+            Request::ServerStopped(reason) => {
+                bail!("could not listen on {}: {}", address, reason);
+            }
+        };
+
+        // the live reload client is watching this, so changing it reloads the pages
+        let mut status = status.lock().unwrap();
+        match result {
+            Ok(()) => {
+                status.build += 1;
+                status.error = None;
+
+                info!(log, "done");
+            }
+            Err(e) => {
+                error!(log, "error building: {:?}", e);
+                status.error = Some(e.to_string());
+            }
         }
     }
 
     Ok(())
 }
+
+/// The directories with the sources of the documented targets in them, and the examples.
+fn source_dirs(config: &Config) -> Result<BTreeSet<PathBuf>> {
+    let metadata = cargo::retrieve_metadata(config.manifest_path())?;
+    let targets =
+        cargo::targets_from_metadata(&metadata, config.manifest_path(), config.api_targets())?;
+
+    let mut dirs: BTreeSet<PathBuf> = targets
+        .iter()
+        .filter_map(|target| target.src_path.parent())
+        .map(Path::to_path_buf)
+        .collect();
+
+    dirs.insert(config.examples_path());
+
+    // a target whose root is in a subdirectory, like `src/bin/tool.rs`, is watched along with
+    // `src` already
+    let nested: Vec<PathBuf> = dirs
+        .iter()
+        .filter(|dir| dirs.iter().any(|other| other != *dir && dir.starts_with(other)))
+        .cloned()
+        .collect();
+
+    for dir in nested {
+        dirs.remove(&dir);
+    }
+
+    Ok(dirs)
+}
+
+/// The files that a watcher event is about.
+fn event_paths(event: DebouncedEvent) -> Vec<PathBuf> {
+    match event {
+        DebouncedEvent::NoticeWrite(path)
+        | DebouncedEvent::NoticeRemove(path)
+        | DebouncedEvent::Create(path)
+        | DebouncedEvent::Write(path)
+        | DebouncedEvent::Chmod(path)
+        | DebouncedEvent::Remove(path) => vec![path],
+        DebouncedEvent::Rename(from, to) => vec![from, to],
+        DebouncedEvent::Rescan | DebouncedEvent::Error(..) => Vec::new(),
+    }
+}
+
+/// Find the first port, starting with `port`, that nothing else is listening on.
+fn free_port(host: &str, port: u16) -> Option<u16> {
+    (0..PORT_ATTEMPTS)
+        .filter_map(|offset| port.checked_add(offset))
+        .find(|&port| TcpListener::bind((host, port)).is_ok())
+}
+
+/// What the path of a request leads to.
+#[derive(Debug, PartialEq)]
+enum Route {
+    File(PathBuf),
+
+    /// A directory, without a `/` on the end. The links in its `index.html` are relative to the
+    /// directory, so they only work once the `/` is added.
+    Redirect(String),
+
+    NotFound,
+}
+
+/// Work out what a request is for, the same way that GitHub Pages does.
+fn route(target_dir: &Path, base_url: &str, url_path: &str) -> Route {
+    let prefix = if base_url.is_empty() {
+        String::from("/")
+    } else {
+        format!("/{}/", base_url)
+    };
+
+    if !url_path.starts_with(&prefix) {
+        if format!("{}/", url_path) == prefix {
+            return Route::Redirect(prefix);
+        }
+
+        return Route::NotFound;
+    }
+
+    let mut path = target_dir.to_path_buf();
+
+    for segment in url_path[prefix.len()..].split('/') {
+        match segment {
+            "" | "." => (),
+            // nothing outside of the docs should be served
+            ".." => return Route::NotFound,
+            segment => path.push(segment),
+        }
+    }
+
+    if path.is_dir() {
+        if !url_path.ends_with('/') {
+            return Route::Redirect(format!("{}/", url_path));
+        }
+
+        path.push("index.html");
+    }
+
+    if path.is_file() {
+        Route::File(path)
+    } else {
+        Route::NotFound
+    }
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = path.extension().and_then(|extension| extension.to_str());
+
+    match extension {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css",
+        Some("js") => "application/javascript",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Make the page that's shown when there's nothing at `url_path`.
+fn not_found_page(url_path: &str, base_url: &str) -> String {
+    let front_page = if base_url.is_empty() {
+        String::from("/index.html")
+    } else {
+        format!("/{}/index.html", base_url)
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <title>Page not found</title>
+</head>
+<body>
+    <h1>Page not found</h1>
+    <p>There's no page at <code>{}</code>. If you've just added it, it'll show up here once
+    the docs have been rebuilt.</p>
+    <p><a href="{}">Go to the front page</a></p>
+</body>
+</html>
+"#,
+        html_escape(url_path),
+        html_escape(&front_page)
+    )
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Add the live reload client to a page, at the end of its body.
+fn add_live_reload(html: &str, build: u64) -> String {
+    let script = LIVE_RELOAD.replace("DOXIDIZE_BUILD", &build.to_string());
+
+    match html.rfind("</body>") {
+        Some(end) => format!("{}{}{}", &html[..end], script, &html[end..]),
+        None => format!("{}{}", html, script),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_live_reload, free_port, not_found_page, route, Route, Snapshot};
+
+    use std::net::TcpListener;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn adds_live_reload_to_the_body() {
+        let html = add_live_reload("<html><body><p>hi</p></body></html>", 3);
+
+        assert!(html.starts_with("<html><body><p>hi</p><script>"));
+        assert!(html.ends_with("</script>\n</body></html>"));
+        assert!(html.contains("var build = 3;"));
+    }
+
+    #[test]
+    fn does_not_serve_files_outside_the_docs() {
+        let docs = Path::new("/docs");
+
+        assert_eq!(route(docs, "", "/../Cargo.toml"), Route::NotFound);
+        assert_eq!(route(docs, "", "/api/../../secret.html"), Route::NotFound);
+    }
+
+    #[test]
+    fn only_serves_files_under_the_base_url() {
+        let docs = Path::new("/docs/foo");
+
+        assert_eq!(route(docs, "foo", "/index.html"), Route::NotFound);
+        assert_eq!(route(docs, "foo", "/food/index.html"), Route::NotFound);
+        assert_eq!(route(docs, "foo", "/foo"), Route::Redirect(String::from("/foo/")));
+    }
+
+    #[test]
+    fn not_found_page_links_to_the_front_page() {
+        let html = not_found_page("/<script>.html", "docs");
+
+        assert!(html.contains("<code>/&lt;script&gt;.html</code>"));
+        assert!(html.contains(r#"<a href="/docs/index.html">"#));
+    }
+
+    #[test]
+    fn skips_ports_that_are_in_use() {
+        let taken = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = taken.local_addr().unwrap().port();
+
+        let free = free_port("127.0.0.1", port).unwrap();
+
+        assert!(free > port);
+    }
+
+    #[test]
+    fn skips_files_that_were_already_built() {
+        let templates = Path::new(env!("CARGO_MANIFEST_DIR")).join("templates");
+        let api = templates.join("markdown").join("api.hbs");
+        let gone = templates.join("gone.md");
+
+        let built = Snapshot::take(&templates);
+
+        assert!(!built.changed(&[api.clone()]));
+        assert!(!built.changed(&[gone.clone()]));
+
+        // files that are different now, or that were built but are gone, need another build
+        let mut stale = Snapshot::default();
+        stale.hashes.insert(api.clone(), 0);
+        stale.hashes.insert(gone.clone(), 0);
+
+        assert!(stale.changed(&[api]));
+        assert!(stale.changed(&[gone]));
+
+        // and so might anything in a directory, or anything at all without a path
+        assert!(built.changed(&[templates.join("markdown")]));
+        assert!(built.changed(&[] as &[PathBuf]));
+    }
+}
@@ -2,14 +2,30 @@ use slog::Logger;
 use walkdir::WalkDir;
 
 use std::collections::HashSet;
-use std::fs::{remove_file, remove_dir};
+use std::fs::{self, remove_dir, remove_file, File};
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 
 use error;
-use ops::init::api;
+use ops::diff;
+use ops::generated::{Change, GeneratedFiles};
+use ops::init::{api, examples};
+use ops::menu;
 use Config;
 use Result;
 
-pub fn update(config: &Config, log: &Logger) -> Result<()> {
+/// How `update` should go about it.
+#[derive(Debug, Default)]
+pub struct UpdateOptions {
+    /// Only report what would change, failing if anything would, rather than changing it
+    pub dry_run: bool,
+
+    /// Replace files without a generated section, rather than reporting them as conflicts
+    pub adopt: bool,
+}
+
+pub fn update(config: &Config, log: &Logger, options: &UpdateOptions) -> Result<()> {
     let log = log.new(o!("command" => "update"));
     info!(log, "starting");
 
@@ -34,27 +50,194 @@ pub fn update(config: &Config, log: &Logger) -> Result<()> {
 
     debug!(log, "recreating api docs");
 
-    let new_files = api::create(config, &log)?;
+    // only the generated sections of existing files are replaced, so anything else that's been
+    // written in them is kept
+    let mut new_files = if options.dry_run {
+        GeneratedFiles::dry_run()
+    } else {
+        GeneratedFiles::new()
+    };
+    new_files.set_adopt(options.adopt);
+
+    api::create(config, &log, &mut new_files)?;
+
+    debug!(log, "recreating example pages");
+
+    let example_ids = examples::create(config, &log, &mut new_files)?;
+
+    warn_about_removed_examples(config, &log, &new_files)?;
+    add_examples_to_menu(config, &log, &mut new_files, &example_ids)?;
 
     debug!(log, "deleting old files and directories that aren't in the new api docs");
 
+    let mut files = Vec::new();
     let mut directories = Vec::new();
 
-    for orphan in existing_files.difference(&new_files) {
+    for orphan in existing_files.difference(new_files.paths()) {
         if orphan.is_dir() {
             // leave directories until later in case we need to delete files within them first
             directories.push(orphan.clone());
         } else {
-            debug!(log, "deleting file"; o!("file" => orphan.display()));
-            remove_file(orphan)?;
+            files.push(orphan.clone());
         }
     }
 
+    if options.dry_run {
+        return report(config, &log, new_files, files, directories);
+    }
+
+    for orphan in files {
+        debug!(log, "deleting file"; o!("file" => orphan.display()));
+        remove_file(orphan)?;
+    }
+
     for orphan_dir in directories {
         debug!(log, "deleting directory"; o!("dir" => orphan_dir.display()));
         remove_dir(&orphan_dir)?;
     }
 
+    // the files with conflicts were left alone, and everything else is up to date, so this is
+    // all that's left to do
+    new_files.check_conflicts()?;
+
     info!(log, "done");
     Ok(())
 }
+
+/// Warn about example pages whose example has been removed.
+///
+/// These aren't deleted like old api docs are, since they may have had more written in them.
+fn warn_about_removed_examples(
+    config: &Config,
+    log: &Logger,
+    new_files: &GeneratedFiles,
+) -> Result<()> {
+    let examples_dir = config.examples_markdown_path();
+
+    if !examples_dir.is_dir() {
+        return Ok(());
+    }
+
+    let mut removed = Vec::new();
+
+    for entry in fs::read_dir(&examples_dir)? {
+        let path = entry?.path();
+
+        if path.extension() == Some("md".as_ref()) && !new_files.paths().contains(&path) {
+            removed.push(path);
+        }
+    }
+
+    removed.sort();
+
+    for path in removed {
+        warn!(log, "the source of this example has been removed, so its page is out of date";
+        "file" => path.display());
+    }
+
+    Ok(())
+}
+
+/// Add any examples that aren't in the menu yet to its "Examples" section.
+fn add_examples_to_menu(
+    config: &Config,
+    log: &Logger,
+    new_files: &mut GeneratedFiles,
+    example_ids: &[String],
+) -> Result<()> {
+    let menu_path = config.menu_path();
+
+    debug!(log, "adding new examples to Menu.toml"; o!("file" => menu_path.display()));
+
+    let mut contents = String::new();
+
+    match File::open(&menu_path).and_then(|mut file| file.read_to_string(&mut contents)) {
+        Ok(_) => (),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+        Err(e) => return Err(e.into()),
+    }
+
+    // an example that's been moved to another section is left where it is
+    let ids: Vec<&str> = example_ids.iter().map(|id| &id[..]).collect();
+    let unlisted = menu::check(&contents, &ids).unlisted;
+
+    if unlisted.is_empty() {
+        return Ok(());
+    }
+
+    for id in &unlisted {
+        trace!(log, "adding example to the menu"; "id" => id);
+    }
+
+    let updated = menu::add_to_section(&contents, "Examples", &unlisted).map_err(|message| {
+        error::Menu {
+            path: menu_path.clone(),
+            line: None,
+            message,
+        }
+    })?;
+
+    new_files.replace(&menu_path, updated)
+}
+
+/// Print what a dry run would have changed, failing if it would have changed anything.
+fn report(
+    config: &Config,
+    log: &Logger,
+    new_files: GeneratedFiles,
+    files: Vec<PathBuf>,
+    directories: Vec<PathBuf>,
+) -> Result<()> {
+    // paths are easier to read relative to the crate
+    let name = |path: &Path| {
+        path.strip_prefix(config.root_path())
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    };
+
+    for change in new_files.changes() {
+        match *change {
+            Change::Create {
+                ref path,
+                ref contents,
+            } => {
+                println!("would create {}", name(path));
+                print!("{}", diff::unified(&name(path), "", contents));
+            }
+            Change::Modify {
+                ref path,
+                ref old,
+                ref new,
+            } => {
+                println!("would modify {}", name(path));
+                print!("{}", diff::unified(&name(path), old, new));
+            }
+        }
+    }
+
+    // these come out of a set, so put them in an order that's easy to read, with each directory
+    // next to what was in it
+    let mut deleted: Vec<_> = files
+        .iter()
+        .map(|path| name(path))
+        .chain(directories.iter().map(|path| format!("{}/", name(path))))
+        .collect();
+    deleted.sort();
+
+    for path in &deleted {
+        println!("would delete {}", path);
+    }
+
+    let changes = new_files.changes().len() + deleted.len();
+
+    // conflicts need fixing by hand, which is more important to hear about
+    new_files.check_conflicts()?;
+
+    if changes > 0 {
+        return Err(error::OutOfDate { changes }.into());
+    }
+
+    info!(log, "the api docs are up to date");
+    Ok(())
+}
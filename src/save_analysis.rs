@@ -0,0 +1,874 @@
+//! Reading the raw save-analysis data that `cargo::generate_analysis` writes out.
+//!
+//! `AnalysisHost` covers most of what we need, but it doesn't keep impl blocks around: a method in
+//! an inherent impl has no parent def, so `for_each_child_def` will never find it. The raw data
+//! records every impl along with its items, so we load it ourselves for those bits, and for
+//! anything else that needs to look at the source itself.
+
+use analysis_data::{Analysis, Def, DefKind, Id, ImplKind, RelationKind};
+use serde_json;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::rc::Rc;
+
+use cargo::{Target, TargetKind};
+use Config;
+use Result;
+
+/// A single impl block, along with the items defined inside of it.
+#[derive(Debug)]
+pub struct ImplBlock<'a> {
+    /// The first line of the impl, like `impl Point`.
+    pub header: String,
+
+    /// The associated functions, constants and types, in source order.
+    pub items: Vec<&'a Def>,
+}
+
+/// Another item that's related to the one being documented, like a trait it implements.
+#[derive(Debug, PartialEq)]
+pub struct RelatedItem {
+    /// The name as it was written in the source, like `fmt::Display` or `Speak`.
+    pub name: String,
+
+    /// The qualname, in the form `AnalysisHost` uses, if the item is defined in this crate.
+    pub qualname: Option<String>,
+}
+
+/// A field of a struct or of a struct-like enum variant.
+#[derive(Debug)]
+pub struct Field<'a> {
+    pub def: &'a Def,
+
+    /// The visibility as written in the source, like `pub` or `pub(crate)`. Empty if private.
+    pub visibility: String,
+}
+
+/// A variant of an enum.
+#[derive(Debug)]
+pub struct Variant<'a> {
+    pub def: &'a Def,
+
+    /// What comes after the name, like `(i32, i32)` or ` { x: i32 }`. Empty for unit variants.
+    pub payload: String,
+}
+
+/// An associated item declared in a trait.
+#[derive(Debug)]
+pub struct TraitItem<'a> {
+    pub def: &'a Def,
+
+    /// Whether the trait gives this item a default, so implementors don't have to.
+    pub provided: bool,
+}
+
+/// The save-analysis data for a single crate.
+pub struct SaveAnalysis {
+    crate_name: String,
+    root_path: PathBuf,
+    analysis: Analysis,
+
+    /// The contents of the source files that have been read so far, or `None` if they couldn't be
+    sources: RefCell<HashMap<PathBuf, Option<Rc<String>>>>,
+}
+
+impl SaveAnalysis {
+    /// Load the data that `cargo::generate_analysis` produced for `target`.
+    pub fn load(config: &Config, target: &Target) -> Result<SaveAnalysis> {
+        let path = find_analysis_file(&config.rls_target_path(), target)?;
+
+        let file = File::open(&path)?;
+        let analysis = serde_json::from_reader(BufReader::new(file))?;
+
+        Ok(SaveAnalysis {
+            crate_name: target.crate_name(),
+            root_path: config.root_path().to_path_buf(),
+            analysis,
+            sources: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Returns the inherent impls of the type with the given qualname.
+    ///
+    /// The qualname is the one `AnalysisHost` gives us, that is, starting with the crate name.
+    pub fn inherent_impls(&self, qualname: &str) -> Vec<ImplBlock> {
+        let self_def = match self.find_def(qualname) {
+            Some(def) => def,
+            None => return Vec::new(),
+        };
+
+        let impl_ids: Vec<u32> = self.analysis
+            .relations
+            .iter()
+            .filter(|relation| relation.from == self_def.id)
+            .filter_map(|relation| match relation.kind {
+                RelationKind::Impl { id } => Some(id),
+                _ => None,
+            })
+            .collect();
+
+        self.analysis
+            .impls
+            .iter()
+            .filter(|imp| impl_ids.contains(&imp.id))
+            .filter(|imp| match imp.kind {
+                ImplKind::Inherent => true,
+                _ => false,
+            })
+            .map(|imp| {
+                let items = imp.children
+                    .iter()
+                    .filter_map(|id| self.analysis.defs.iter().find(|def| def.id == *id))
+                    .filter(|def| match def.kind {
+                        DefKind::Method | DefKind::Const | DefKind::Type => true,
+                        _ => false,
+                    })
+                    .collect();
+
+                let header = self.impl_header(imp.id)
+                    .unwrap_or_else(|| format!("impl {}", self_def.name));
+
+                ImplBlock { header, items }
+            })
+            .collect()
+    }
+
+    /// Returns the traits that the type with the given qualname implements, including derives.
+    ///
+    /// `crates` is every crate being documented, so that traits from the others can be linked.
+    pub fn trait_impls(&self, qualname: &str, crates: &[SaveAnalysis]) -> Vec<RelatedItem> {
+        let self_def = match self.find_def(qualname) {
+            Some(def) => def,
+            None => return Vec::new(),
+        };
+
+        let mut traits: Vec<RelatedItem> = self.analysis
+            .relations
+            .iter()
+            .filter(|relation| relation.from == self_def.id && !is_null(relation.to))
+            .filter_map(|relation| {
+                let impl_id = match relation.kind {
+                    RelationKind::Impl { id } => id,
+                    _ => return None,
+                };
+
+                match self.resolve(relation.to, crates) {
+                    Some((owner, def)) => Some(owner.related_item(def)),
+                    None => {
+                        // the trait is from a crate we aren't documenting, so the source is all
+                        // we have
+                        let header = self.impl_header(impl_id)?;
+                        let (trait_name, _) = split_impl_header(&header)?;
+
+                        Some(RelatedItem {
+                            name: trait_name,
+                            qualname: None,
+                        })
+                    }
+                }
+            })
+            .collect();
+
+        // derived impls are generated code, which the analysis skips, so we look for the
+        // attribute ourselves
+        if let Some(source) = self.read_source(&self_def.span.file_name) {
+            let line = self_def.span.line_start.0 as usize;
+            let lines: Vec<&str> = source.lines().take(line.saturating_sub(1)).collect();
+
+            for name in derives(&lines) {
+                if !traits.iter().any(|t| t.name == name) {
+                    traits.push(RelatedItem {
+                        name,
+                        qualname: None,
+                    });
+                }
+            }
+        }
+
+        traits
+    }
+
+    /// Returns the types that implement the trait with the given qualname.
+    ///
+    /// `crates` is every crate being documented, including this one; impls of the trait may be in
+    /// any of them.
+    pub fn implementors(&self, qualname: &str, crates: &[SaveAnalysis]) -> Vec<RelatedItem> {
+        let trait_def = match self.find_def(qualname) {
+            Some(def) => def,
+            None => return Vec::new(),
+        };
+
+        let mut implementors = Vec::new();
+
+        for krate in crates {
+            for relation in &krate.analysis.relations {
+                let impl_id = match relation.kind {
+                    RelationKind::Impl { id } => id,
+                    _ => continue,
+                };
+
+                let is_this_trait = match krate.resolve(relation.to, crates) {
+                    Some((owner, def)) => ptr::eq(owner, self) && def.id == trait_def.id,
+                    None => false,
+                };
+
+                if !is_this_trait {
+                    continue;
+                }
+
+                let implementor = match krate.resolve(relation.from, crates) {
+                    Some((owner, def)) => owner.related_item(def),
+                    None => {
+                        let header = krate.impl_header(impl_id);
+                        match header.as_ref().and_then(|header| split_impl_header(header)) {
+                            Some((_, self_name)) => RelatedItem {
+                                name: self_name,
+                                qualname: None,
+                            },
+                            None => continue,
+                        }
+                    }
+                };
+
+                implementors.push(implementor);
+            }
+        }
+
+        implementors
+    }
+
+    /// Returns the fields of the struct with the given qualname, in source order.
+    pub fn fields(&self, qualname: &str) -> Vec<Field> {
+        match self.find_def(qualname) {
+            Some(def) => self.child_fields(def),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the variants of the enum with the given qualname, in source order.
+    pub fn variants(&self, qualname: &str) -> Vec<Variant> {
+        let enum_def = match self.find_def(qualname) {
+            Some(def) => def,
+            None => return Vec::new(),
+        };
+
+        enum_def
+            .children
+            .iter()
+            .filter_map(|id| self.local_def(*id))
+            .filter_map(|def| {
+                let payload = match def.kind {
+                    DefKind::StructVariant => {
+                        let fields: Vec<String> = self.child_fields(def)
+                            .iter()
+                            .map(|field| format!("{}: {}", field.def.name, field.def.value))
+                            .collect();
+
+                        format!(" {{ {} }}", fields.join(", "))
+                    }
+                    // the value looks like `TrafficLight::Red(i32)`, or is empty for unit variants
+                    DefKind::TupleVariant => match def.value.find('(') {
+                        Some(index) => def.value[index..].to_string(),
+                        None => String::new(),
+                    },
+                    _ => return None,
+                };
+
+                Some(Variant { def, payload })
+            })
+            .collect()
+    }
+
+    /// Returns the associated items of the trait with the given qualname, in source order.
+    pub fn trait_items(&self, qualname: &str) -> Vec<TraitItem> {
+        let trait_def = match self.find_def(qualname) {
+            Some(def) => def,
+            None => return Vec::new(),
+        };
+
+        trait_def
+            .children
+            .iter()
+            .filter_map(|id| self.local_def(*id))
+            .filter(|def| match def.kind {
+                DefKind::Method | DefKind::Const | DefKind::Type => true,
+                _ => false,
+            })
+            .map(|def| {
+                // the analysis doesn't say whether there's a body, so we check the source
+                let provided = self.read_source(&def.span.file_name)
+                    .and_then(|source| {
+                        let declaration = source.get(def.span.byte_start as usize..)?;
+                        Some(has_default(declaration))
+                    })
+                    .unwrap_or(false);
+
+                TraitItem { def, provided }
+            })
+            .collect()
+    }
+
+    fn child_fields(&self, parent: &Def) -> Vec<Field> {
+        parent
+            .children
+            .iter()
+            .filter_map(|id| self.local_def(*id))
+            .filter(|def| match def.kind {
+                DefKind::Field => true,
+                _ => false,
+            })
+            .map(|def| {
+                // the field's span starts at its name, so its visibility comes right before it
+                let visibility = self.read_source(&def.span.file_name)
+                    .and_then(|source| {
+                        let before = source.get(..def.span.byte_start as usize)?;
+                        Some(visibility(before))
+                    })
+                    .unwrap_or_default();
+
+                Field { def, visibility }
+            })
+            .collect()
+    }
+
+    /// Finds a def by the qualname `AnalysisHost` would give it.
+    ///
+    /// The raw data leaves off the crate name, so `::examples::Point` rather than
+    /// `doxidize::examples::Point`.
+    fn find_def(&self, qualname: &str) -> Option<&Def> {
+        let raw_qualname = raw_qualname(&self.crate_name, qualname)?;
+
+        self.analysis
+            .defs
+            .iter()
+            .find(|def| def.qualname == raw_qualname)
+    }
+
+    /// Finds a def in this crate by its id.
+    fn local_def(&self, id: Id) -> Option<&Def> {
+        self.analysis.defs.iter().find(|def| def.id == id)
+    }
+
+    /// Finds the def for an id in this crate's data, which may belong to one of the other `crates`.
+    fn resolve<'a>(
+        &'a self,
+        id: Id,
+        crates: &'a [SaveAnalysis],
+    ) -> Option<(&'a SaveAnalysis, &'a Def)> {
+        if id.krate == LOCAL_CRATE {
+            return self.local_def(id).map(|def| (self, def));
+        }
+
+        // ids from other crates use a number that's only meaningful to this crate, so we need to
+        // find its name, and then look up the def with the same index over there
+        let prelude = self.analysis.prelude.as_ref()?;
+        let external = prelude
+            .external_crates
+            .iter()
+            .find(|krate| krate.num == id.krate)?;
+
+        let owner = crates
+            .iter()
+            .find(|krate| krate.crate_name == external.id.name)?;
+
+        let def = owner.local_def(Id {
+            krate: LOCAL_CRATE,
+            index: id.index,
+        })?;
+
+        Some((owner, def))
+    }
+
+    fn related_item(&self, def: &Def) -> RelatedItem {
+        RelatedItem {
+            name: def.name.clone(),
+            qualname: Some(format!("{}{}", self.crate_name, def.qualname)),
+        }
+    }
+
+    /// Returns the first line of the impl with the given id, like `impl Speak for Point`.
+    fn impl_header(&self, impl_id: u32) -> Option<String> {
+        let imp = self.analysis.impls.iter().find(|imp| imp.id == impl_id)?;
+
+        let source = self.read_source(&imp.span.file_name)?;
+
+        source
+            .lines()
+            .nth((imp.span.line_start.0 as usize).checked_sub(1)?)
+            .map(impl_header)
+    }
+
+    /// Read a source file the analysis refers to.
+    ///
+    /// Every impl, field and trait item needs the source, so each file is only read once.
+    fn read_source(&self, file_name: &Path) -> Option<Rc<String>> {
+        let mut sources = self.sources.borrow_mut();
+
+        sources
+            .entry(file_name.to_path_buf())
+            .or_insert_with(|| {
+                // file names are relative to the root of the crate, unless they're absolute
+                let mut file = File::open(self.root_path.join(file_name)).ok()?;
+                let mut contents = String::new();
+                file.read_to_string(&mut contents).ok()?;
+
+                Some(Rc::new(contents))
+            })
+            .clone()
+    }
+}
+
+/// Turn a qualname from `AnalysisHost` into the form the raw data uses, if it's in this crate.
+fn raw_qualname<'a>(crate_name: &str, qualname: &'a str) -> Option<&'a str> {
+    if !qualname.starts_with(crate_name) {
+        return None;
+    }
+
+    let raw_qualname = &qualname[crate_name.len()..];
+
+    // `doxidize_foo::X` isn't in the `doxidize` crate
+    if raw_qualname.is_empty() || raw_qualname.starts_with("::") {
+        Some(raw_qualname)
+    } else {
+        None
+    }
+}
+
+/// Render the signature of a method, given its name and the value the analysis has for it.
+///
+/// The analysis leaves the name out, giving us something like `fn (&self) -> ()`.
+pub fn method_signature(name: &str, value: &str) -> String {
+    if value.starts_with("fn ") {
+        format!("fn {}{}", name, &value[3..])
+    } else {
+        value.to_string()
+    }
+}
+
+/// Turn the first line of an impl into something that can be used as a heading.
+fn impl_header(line: &str) -> String {
+    line.trim().trim_right_matches('{').trim().to_string()
+}
+
+/// Find the visibility of a field, given the source that comes before its name.
+fn visibility(before: &str) -> String {
+    let before = before.trim_right();
+
+    // a restricted visibility, like `pub(crate)`, ends with the parenthesis
+    let end = if before.ends_with(')') {
+        match before.rfind('(') {
+            Some(index) => index,
+            None => return String::new(),
+        }
+    } else {
+        before.len()
+    };
+
+    let keyword = before[..end].trim_right();
+    let is_pub = keyword.ends_with("pub") && !keyword[..keyword.len() - 3]
+        .chars()
+        .next_back()
+        .map_or(false, |c| c.is_alphanumeric() || c == '_');
+
+    if is_pub {
+        before[keyword.len() - 3..].to_string()
+    } else {
+        String::new()
+    }
+}
+
+/// Does the trait item declared at the start of `declaration` come with a default?
+///
+/// Required items end in a `;`, where provided ones have a body or a `= value` first.
+fn has_default(declaration: &str) -> bool {
+    let mut depth = 0;
+    let mut previous = ' ';
+
+    for c in declaration.chars() {
+        match c {
+            // the arrow of a return type isn't closing anything
+            '>' if previous == '-' => (),
+            '(' | '[' | '<' => depth += 1,
+            ')' | ']' | '>' => depth -= 1,
+            '{' | '=' if depth == 0 => return true,
+            ';' if depth == 0 => return false,
+            _ => (),
+        }
+
+        previous = c;
+    }
+
+    false
+}
+
+/// Split an impl header like `impl<T> Speak for Vec<T>` into the trait and the type.
+///
+/// Returns `None` for inherent impls.
+fn split_impl_header(header: &str) -> Option<(String, String)> {
+    let rest = header.trim().trim_left_matches("impl");
+
+    // skip over the generics, if there are any; they may contain `for` themselves
+    let mut depth = 0;
+    let mut start = 0;
+    let mut previous = ' ';
+    for (i, c) in rest.char_indices() {
+        match c {
+            '<' => depth += 1,
+            // the `>` of an `->`, like in `impl<F: Fn() -> u8>`, doesn't close anything
+            '>' if previous != '-' => depth -= 1,
+            c if depth == 0 && !c.is_whitespace() => {
+                start = i;
+                break;
+            }
+            _ => (),
+        }
+        previous = c;
+    }
+    let rest = &rest[start..];
+
+    let index = rest.find(" for ")?;
+    let trait_name = rest[..index].trim();
+    let self_name = rest[(index + 5)..].split(" where").next()?.trim();
+
+    Some((trait_name.to_string(), self_name.to_string()))
+}
+
+/// Collect the traits named in `#[derive]` attributes directly above an item, including ones
+/// inside a `cfg_attr`.
+///
+/// `lines` are the source lines that come before the item.
+fn derives(lines: &[&str]) -> Vec<String> {
+    // attributes can span lines, so first find where the attributes and doc comments start: the
+    // line after the end of the previous item
+    let start = lines
+        .iter()
+        .rposition(|line| {
+            let line = line.trim();
+            !line.starts_with("//")
+                && (line.ends_with(';') || line.ends_with('}') || line.ends_with('{'))
+        })
+        .map(|index| index + 1)
+        .unwrap_or(0);
+
+    let block: Vec<&str> = lines[start..]
+        .iter()
+        .map(|line| strip_comment(line).trim())
+        .collect();
+    let block = block.join(" ");
+
+    let mut names = Vec::new();
+
+    for attribute in attributes(&block) {
+        for list in derive_lists(attribute) {
+            names.extend(
+                split_top_level(list)
+                    .into_iter()
+                    .map(|name| name.trim().to_string())
+                    .filter(|name| !name.is_empty()),
+            );
+        }
+    }
+
+    names
+}
+
+/// The contents of each outer attribute in some source, like `derive(Debug)` for
+/// `#[derive(Debug)]`, in order.
+fn attributes(source: &str) -> Vec<&str> {
+    let mut attributes = Vec::new();
+    let mut rest = source;
+
+    while let Some(index) = rest.find("#[") {
+        let contents = &rest[index + 2..];
+
+        match closing(contents, '[', ']') {
+            Some(end) => {
+                attributes.push(contents[..end].trim());
+                rest = &contents[end + 1..];
+            }
+            None => break,
+        }
+    }
+
+    attributes
+}
+
+/// The lists of traits in the `derive`s in an attribute, looking inside `cfg_attr`s.
+fn derive_lists(attribute: &str) -> Vec<&str> {
+    let (name, arguments) = match attribute.find('(') {
+        Some(index) => (attribute[..index].trim(), &attribute[index + 1..]),
+        None => return Vec::new(),
+    };
+
+    let arguments = match closing(arguments, '(', ')') {
+        Some(end) => &arguments[..end],
+        None => return Vec::new(),
+    };
+
+    match name {
+        "derive" => vec![arguments],
+        // the first argument is the condition, and the rest are attributes
+        "cfg_attr" => split_top_level(arguments)
+            .into_iter()
+            .skip(1)
+            .flat_map(|attribute| derive_lists(attribute.trim()))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Find the `close` that matches an `open` just before the start of `text`, skipping over
+/// string literals.
+fn closing(text: &str, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                '\\' if !escaped => escaped = true,
+                '"' if !escaped => in_string = false,
+                _ => escaped = false,
+            }
+            continue;
+        }
+
+        if c == '"' {
+            in_string = true;
+        } else if c == open {
+            depth += 1;
+        } else if c == close {
+            if depth == 0 {
+                return Some(i);
+            }
+            depth -= 1;
+        }
+    }
+
+    None
+}
+
+/// Remove a `//` comment from the end of a line of source, if there is one.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut previous = ' ';
+
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' if previous != '\\' => in_string = !in_string,
+            '/' if previous == '/' && !in_string => return &line[..i - 1],
+            _ => (),
+        }
+        previous = c;
+    }
+
+    line
+}
+
+/// Split a list on the commas that aren't nested inside brackets.
+fn split_top_level(list: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, c) in list.char_indices() {
+        match c {
+            '(' | '[' | '<' => depth += 1,
+            ')' | ']' | '>' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&list[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+
+    parts.push(&list[start..]);
+    parts
+}
+
+/// The crate number that ids from the crate being analyzed have.
+const LOCAL_CRATE: u32 = 0;
+
+/// save-analysis uses an id with every bit set to mean "nothing", like for the trait of an
+/// inherent impl.
+fn is_null(id: Id) -> bool {
+    id.krate == u32::max_value() && id.index == u32::max_value()
+}
+
+/// Find the newest save-analysis file for the given target.
+///
+/// `cargo check` leaves old files around when the crate changes, so there may be more than one.
+fn find_analysis_file(rls_target_path: &Path, target: &Target) -> Result<PathBuf> {
+    let dir = rls_target_path
+        .join("debug")
+        .join("deps")
+        .join("save-analysis");
+
+    let prefix = match target.kind {
+        TargetKind::Library => format!("lib{}-", target.crate_name()),
+        TargetKind::Binary => format!("{}-", target.crate_name()),
+    };
+
+    let mut newest = None;
+
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        let is_match = path.file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| name.starts_with(&prefix) && name.ends_with(".json"))
+            .unwrap_or(false);
+
+        if !is_match {
+            continue;
+        }
+
+        let modified = entry.metadata()?.modified()?;
+
+        newest = match newest {
+            Some((newest_modified, _)) if newest_modified >= modified => newest,
+            _ => Some((modified, path)),
+        };
+    }
+
+    match newest {
+        Some((_, path)) => Ok(path),
+        None => bail!(
+            "could not find save-analysis data for `{}` in {}",
+            target.name,
+            dir.display()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{derives, has_default, impl_header, method_signature, raw_qualname,
+                split_impl_header, visibility};
+
+    #[test]
+    fn raw_qualnames_stop_at_the_crate_name() {
+        assert_eq!(raw_qualname("doxidize", "doxidize::Point"), Some("::Point"));
+        assert_eq!(raw_qualname("doxidize", "doxidize"), Some(""));
+        assert_eq!(raw_qualname("doxidize", "doxidize_foo::Point"), None);
+        assert_eq!(raw_qualname("doxidize", "other::Point"), None);
+    }
+
+    #[test]
+    fn method_signature_includes_name() {
+        assert_eq!(method_signature("new", "fn () -> Point"), "fn new() -> Point");
+        assert_eq!(method_signature("speak", "fn (&self) -> ()"), "fn speak(&self) -> ()");
+    }
+
+    #[test]
+    fn method_signature_leaves_other_values_alone() {
+        assert_eq!(method_signature("ORIGIN", "Point"), "Point");
+    }
+
+    #[test]
+    fn impl_header_strips_brace() {
+        assert_eq!(impl_header("impl Point {"), "impl Point");
+        assert_eq!(impl_header("    impl<T> Wrapper<T> {  "), "impl<T> Wrapper<T>");
+        assert_eq!(impl_header("impl Point"), "impl Point");
+    }
+
+    #[test]
+    fn split_impl_header_finds_trait_and_type() {
+        assert_eq!(
+            split_impl_header("impl Speak for Point"),
+            Some(("Speak".to_string(), "Point".to_string()))
+        );
+        assert_eq!(
+            split_impl_header("impl<T: Clone> fmt::Debug for Wrapper<T> where T: Copy"),
+            Some(("fmt::Debug".to_string(), "Wrapper<T>".to_string()))
+        );
+        assert_eq!(split_impl_header("impl Point"), None);
+    }
+
+    #[test]
+    fn split_impl_header_skips_arrows_in_generics() {
+        assert_eq!(
+            split_impl_header("impl<F: Fn() -> u8> Speak for F"),
+            Some(("Speak".to_string(), "F".to_string()))
+        );
+    }
+
+    #[test]
+    fn derives_are_collected_in_order() {
+        let lines = vec![
+            "pub const ALWAYS_FIVE: i32 = 5;",
+            "",
+            "/// A point in space",
+            "#[derive(Debug, Clone)]",
+            "#[derive(PartialEq)]",
+        ];
+
+        assert_eq!(derives(&lines), vec!["Debug", "Clone", "PartialEq"]);
+    }
+
+    #[test]
+    fn derives_can_span_lines() {
+        let lines = vec![
+            "pub struct Other;",
+            "#[derive(",
+            "    Debug,",
+            "    Clone, // for tests",
+            ")]",
+        ];
+
+        assert_eq!(derives(&lines), vec!["Debug", "Clone"]);
+    }
+
+    #[test]
+    fn derives_inside_cfg_attr() {
+        let lines = vec![
+            "#[cfg_attr(feature = \"serde\", derive(Serialize, Deserialize))]",
+            "#[derive(Debug)]",
+        ];
+
+        assert_eq!(derives(&lines), vec!["Serialize", "Deserialize", "Debug"]);
+    }
+
+    #[test]
+    fn derives_stop_at_previous_item() {
+        let lines = vec!["#[derive(Debug)]", "pub struct Other;", "/// docs"];
+
+        assert!(derives(&lines).is_empty());
+    }
+
+    #[test]
+    fn visibility_of_fields() {
+        assert_eq!(visibility("    pub "), "pub");
+        assert_eq!(visibility("    pub(crate) "), "pub(crate)");
+        assert_eq!(visibility("    pub(in ::foo) "), "pub(in ::foo)");
+        assert_eq!(visibility("    "), "");
+
+        // only what's right before the name counts
+        assert_eq!(visibility("    pub ax: i32, "), "");
+        assert_eq!(visibility("    #[doc = \"pub\"] "), "");
+        assert_eq!(visibility("struct Point { pub x: i32, pub "), "pub");
+        assert_eq!(visibility("    republic_"), "");
+    }
+
+    #[test]
+    fn required_trait_items_have_no_default() {
+        assert!(!has_default("speak(&self);\n}"));
+        assert!(!has_default("MAX: [u8; 4];"));
+        assert!(!has_default("Item;"));
+    }
+
+    #[test]
+    fn provided_trait_items_have_a_default() {
+        assert!(has_default("shout(&self) -> String {\n        self.speak()\n    }"));
+        assert!(has_default("MAX: u32 = 5;"));
+    }
+}
@@ -0,0 +1,127 @@
+extern crate doxidize;
+
+#[macro_use]
+extern crate slog;
+extern crate slog_async;
+extern crate slog_term;
+
+extern crate tempdir;
+
+use doxidize::ops::ApiDiffOptions;
+use doxidize::Config;
+use tempdir::TempDir;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use std::process::Command;
+
+mod util;
+
+/// Replace the sample crate's `main.rs`.
+fn write_main(dir: &Path, contents: &str) {
+    File::create(dir.join("src").join("main.rs"))
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .expect("could not write main.rs");
+}
+
+fn git(dir: &Path, args: &[&str]) {
+    let output = Command::new("git")
+        .args(&["-c", "user.name=Doxidize", "-c", "user.email=doxidize@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .expect("failed to execute git");
+
+    assert!(
+        output.status.success(),
+        "git {:?} failed:\n{}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn reports_added_items() {
+    let dir = TempDir::new("api_diff").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    let dir_path = dir.path();
+
+    util::cargo_init(dir_path).expect("Could not create sample crate");
+
+    git(dir_path, &["init"]);
+    git(dir_path, &["add", "."]);
+    git(dir_path, &["commit", "-m", "first"]);
+
+    let mut main = String::new();
+    File::open(dir_path.join("src").join("main.rs"))
+        .and_then(|mut file| file.read_to_string(&mut main))
+        .expect("could not read main.rs");
+
+    write_main(dir_path, &format!("{}\npub fn added(verbose: bool) {{}}\n", main));
+
+    git(dir_path, &["commit", "-am", "second"]);
+
+    let config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    let options = ApiDiffOptions {
+        old: String::from("HEAD~1"),
+        new: String::from("HEAD"),
+        write_page: true,
+    };
+
+    doxidize::ops::api_diff(&config, &log, &options).expect("api-diff failed");
+
+    let mut page = String::new();
+    File::open(config.api_changes_path())
+        .and_then(|mut file| file.read_to_string(&mut page))
+        .expect("could not read the changes");
+
+    assert!(page.contains("## Added"), "{}", page);
+    assert!(page.contains("`example::added`"), "{}", page);
+    assert!(!page.contains("## Removed"), "{}", page);
+}
+
+#[test]
+fn reports_changed_method_signatures() {
+    let dir = TempDir::new("api_diff_methods").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    let dir_path = dir.path();
+
+    util::cargo_init(dir_path).expect("Could not create sample crate");
+
+    let main = "pub struct Point;\n\nimpl Point {\n    pub fn shift(&self) {}\n}\n\nfn main() {}\n";
+    write_main(dir_path, main);
+
+    git(dir_path, &["init"]);
+    git(dir_path, &["add", "."]);
+    git(dir_path, &["commit", "-m", "first"]);
+
+    write_main(dir_path, &main.replace("shift(&self)", "shift(&self, by: u8)"));
+
+    git(dir_path, &["commit", "-am", "second"]);
+
+    let config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    let options = ApiDiffOptions {
+        old: String::from("HEAD~1"),
+        new: String::from("HEAD"),
+        write_page: true,
+    };
+
+    doxidize::ops::api_diff(&config, &log, &options).expect("api-diff failed");
+
+    let mut page = String::new();
+    File::open(config.api_changes_path())
+        .and_then(|mut file| file.read_to_string(&mut page))
+        .expect("could not read the changes");
+
+    assert!(page.contains("## Changed"), "{}", page);
+    assert!(page.contains("method `example::Point::shift`"), "{}", page);
+    assert!(page.contains("by: u8"), "{}", page);
+
+    // the struct itself didn't change
+    assert!(!page.contains("struct `example::Point`"), "{}", page);
+}
@@ -184,3 +184,248 @@ testing"#,
 "
     ));
 }
+
+#[test]
+fn build_reports_every_front_matter_problem() {
+    let dir = TempDir::new("front_matter_problems").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    let dir_path = dir.path();
+
+    util::cargo_init(dir_path).expect("Could not create sample crate");
+
+    let config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    doxidize::ops::init(&config, &log).expect("init failed");
+
+    let docs_dir = dir_path.join("docs");
+
+    let mut unclosed = File::create(docs_dir.join("unclosed.md")).expect("could not create file");
+    unclosed
+        .write_all(
+            br#"---
+id = "unclosed"
+title = "Unclosed"
+# Testing"#,
+        )
+        .expect("could not write to unclosed.md");
+
+    let mut missing = File::create(docs_dir.join("missing.md")).expect("could not create file");
+    missing
+        .write_all(b"# No front matter here")
+        .expect("could not write to missing.md");
+
+    let error = doxidize::ops::build(&config, &log).expect_err("build should have failed");
+    let message = error.to_string();
+
+    assert!(message.contains("unclosed.md:1"), "{}", message);
+    assert!(message.contains("missing.md:1"), "{}", message);
+}
+
+#[test]
+fn build_uses_custom_docs_and_output_dirs() {
+    let dir = TempDir::new("custom_dirs").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    let dir_path = dir.path();
+
+    util::cargo_init(dir_path).expect("Could not create sample crate");
+
+    let mut config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+    config.set_markdown_path(dir_path.join("guide"));
+    config.set_output_path(dir_path.join("public"));
+
+    doxidize::ops::init(&config, &log).expect("init failed");
+
+    assert!(dir_path.join("guide").join("README.md").is_file());
+    assert!(!dir_path.join("docs").exists());
+
+    // init records the paths, so we shouldn't need to set them again
+    let config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    doxidize::ops::build(&config, &log).expect("build failed");
+
+    assert!(dir_path.join("public").join("index.html").is_file());
+    assert!(!dir_path.join("target").join("docs").exists());
+}
+
+#[test]
+fn build_writes_search_index() {
+    let dir = TempDir::new("search_index").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    let dir_path = dir.path();
+
+    util::cargo_init(dir_path).expect("Could not create sample crate");
+
+    let config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    doxidize::ops::init(&config, &log).expect("init failed");
+    doxidize::ops::build(&config, &log).expect("build failed");
+
+    let index_path = dir_path.join("target").join("docs").join("search-index.js");
+
+    let mut index = String::new();
+    File::open(index_path)
+        .expect("could not open search index")
+        .read_to_string(&mut index)
+        .expect("could not read search index");
+
+    assert!(index.starts_with("window.searchIndex = "));
+    assert!(index.contains(r#""title":"Overview""#));
+    assert!(index.contains(r#""url":"index.html""#));
+}
+
+#[test]
+fn check_reports_dead_links() {
+    let dir = TempDir::new("dead_links").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    let dir_path = dir.path();
+
+    util::cargo_init(dir_path).expect("Could not create sample crate");
+
+    let config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    doxidize::ops::init(&config, &log).expect("init failed");
+    doxidize::ops::build(&config, &log).expect("build failed");
+    doxidize::ops::check(&config, &log).expect("a new project should have no dead links");
+
+    let mut guide = File::create(dir_path.join("docs").join("guide.md"))
+        .expect("could not create guide");
+    guide
+        .write_all(
+            br#"---
+id = "guide"
+title = "Guide"
+---
+# Guide
+
+[Overview](index.html) and [this page](gui%64e.html) and [nowhere](nowhere.html) and
+[nothing](#nothing)"#,
+        )
+        .expect("could not write to guide");
+
+    doxidize::ops::build(&config, &log).expect("build failed");
+
+    let error = doxidize::ops::check(&config, &log).expect_err("check should have failed");
+    let message = error.to_string();
+
+    assert!(message.contains("found 2 dead link(s)"), "{}", message);
+    assert!(message.contains("guide.md"), "{}", message);
+    assert!(message.contains("`nowhere.html`"), "{}", message);
+    assert!(message.contains("`#nothing`"), "{}", message);
+}
+
+#[test]
+fn build_skips_unchanged_pages() {
+    let dir = TempDir::new("incremental").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    let dir_path = dir.path();
+
+    util::cargo_init(dir_path).expect("Could not create sample crate");
+
+    let config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    doxidize::ops::init(&config, &log).expect("init failed");
+
+    let guide_path = dir_path.join("docs").join("guide.md");
+    let guide = br#"---
+id = "guide"
+title = "Guide"
+---
+# Guide"#;
+
+    File::create(&guide_path)
+        .and_then(|mut file| file.write_all(guide))
+        .expect("could not write guide");
+
+    doxidize::ops::build(&config, &log).expect("build failed");
+
+    // if the page is skipped, this will still be here after the next build
+    let rendered_guide_path = dir_path.join("target").join("docs").join("guide.html");
+    File::create(&rendered_guide_path)
+        .and_then(|mut file| file.write_all(b"stale"))
+        .expect("could not overwrite rendered guide");
+
+    doxidize::ops::build(&config, &log).expect("build failed");
+
+    let read_rendered_guide = || {
+        let mut contents = String::new();
+        File::open(&rendered_guide_path)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .expect("could not read rendered guide");
+        contents
+    };
+
+    assert_eq!(read_rendered_guide(), "stale");
+
+    OpenOptions::new()
+        .append(true)
+        .open(&guide_path)
+        .and_then(|mut file| file.write_all(b"\n\nchanged"))
+        .expect("could not change guide");
+
+    doxidize::ops::build(&config, &log).expect("build failed");
+
+    assert!(read_rendered_guide().contains("<p>changed</p>"));
+}
+
+#[test]
+fn build_output_does_not_depend_on_jobs() {
+    let dir = TempDir::new("jobs").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    let dir_path = dir.path();
+
+    util::cargo_init(dir_path).expect("Could not create sample crate");
+
+    let mut config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    doxidize::ops::init(&config, &log).expect("init failed");
+
+    for name in &["one", "two", "three", "four", "five"] {
+        let contents = format!(
+            "---\nid = \"{0}\"\ntitle = \"{0}\"\n---\n# {0}\n\n[`unknown`]",
+            name
+        );
+
+        File::create(dir_path.join("docs").join(format!("{}.md", name)))
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .expect("could not write page");
+    }
+
+    let read_output = |output: &str| {
+        let files = [
+            "index.html",
+            "one.html",
+            "two.html",
+            "three.html",
+            "four.html",
+            "five.html",
+            "search-index.js",
+        ];
+
+        files
+            .iter()
+            .map(|file| {
+                let mut contents = String::new();
+                File::open(dir_path.join(output).join(file))
+                    .and_then(|mut file| file.read_to_string(&mut contents))
+                    .expect("could not read output");
+                contents
+            })
+            .collect::<Vec<_>>()
+    };
+
+    config.set_jobs(1);
+    config.set_output_path(dir_path.join("one-job"));
+    doxidize::ops::build(&config, &log).expect("build failed");
+
+    config.set_jobs(4);
+    config.set_output_path(dir_path.join("four-jobs"));
+    doxidize::ops::build(&config, &log).expect("build failed");
+
+    assert_eq!(read_output("one-job"), read_output("four-jobs"));
+}
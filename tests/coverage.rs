@@ -0,0 +1,51 @@
+extern crate doxidize;
+
+#[macro_use]
+extern crate slog;
+extern crate slog_async;
+extern crate slog_term;
+
+extern crate tempdir;
+
+use doxidize::ops::CoverageOptions;
+use doxidize::Config;
+use tempdir::TempDir;
+
+use std::fs::File;
+use std::io::prelude::*;
+
+mod util;
+
+#[test]
+fn fail_under_fails_when_docs_are_missing() {
+    let dir = TempDir::new("coverage_fail_under").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    let dir_path = dir.path();
+
+    util::cargo_init(dir_path).expect("Could not create sample crate");
+
+    let config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    let options = CoverageOptions {
+        fail_under: Some(100.0),
+        ..CoverageOptions::default()
+    };
+
+    // the crate itself is the only public item, and it has no docs yet
+    let error =
+        doxidize::ops::coverage(&config, &log, &options).expect_err("coverage should have failed");
+    assert!(error.to_string().contains("only 0.0%"), "{}", error);
+
+    let main_path = dir_path.join("src").join("main.rs");
+    let mut main = String::new();
+    File::open(&main_path)
+        .and_then(|mut file| file.read_to_string(&mut main))
+        .expect("could not read main.rs");
+
+    File::create(&main_path)
+        .and_then(|mut file| write!(file, "//! Some docs.\n\n{}", main))
+        .expect("could not add docs");
+
+    doxidize::ops::coverage(&config, &log, &options).expect("everything is documented");
+}
@@ -0,0 +1,88 @@
+extern crate doxidize;
+
+#[macro_use]
+extern crate slog;
+extern crate slog_async;
+extern crate slog_term;
+
+extern crate tempdir;
+
+use doxidize::Config;
+use tempdir::TempDir;
+
+use std::fs::OpenOptions;
+use std::io::prelude::*;
+use std::path::Path;
+use std::process::Command;
+
+mod util;
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(&["-c", "user.name=Doxidize", "-c", "user.email=doxidize@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .expect("failed to execute git");
+
+    assert!(
+        output.status.success(),
+        "git {:?} failed:\n{}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+#[test]
+fn publish_carries_on_after_clean() {
+    let dir = TempDir::new("publish").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    // publish commits as whoever runs it
+    for &(key, value) in &[
+        ("GIT_AUTHOR_NAME", "Doxidize"),
+        ("GIT_AUTHOR_EMAIL", "doxidize@example.com"),
+        ("GIT_COMMITTER_NAME", "Doxidize"),
+        ("GIT_COMMITTER_EMAIL", "doxidize@example.com"),
+    ] {
+        ::std::env::set_var(key, value);
+    }
+
+    git(dir.path(), &["init", "--bare", "origin.git"]);
+    let origin = dir.path().join("origin.git");
+
+    // the crate is in a subdirectory of its repository
+    let repo_path = dir.path().join("project");
+    let dir_path = repo_path.join("example");
+    ::std::fs::create_dir_all(&dir_path).expect("could not create crate dir");
+
+    git(&repo_path, &["init"]);
+    util::cargo_init(&dir_path).expect("Could not create sample crate");
+    git(&repo_path, &["add", "."]);
+    git(&repo_path, &["commit", "-m", "first"]);
+    git(&repo_path, &["remote", "add", "origin", &origin.to_string_lossy()]);
+
+    let config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    doxidize::ops::init(&config, &log).expect("init failed");
+    doxidize::ops::build(&config, &log).expect("build failed");
+    doxidize::ops::publish(&config, &log).expect("publish failed");
+
+    git(&origin, &["cat-file", "-e", "gh-pages:index.html"]);
+
+    // the repository that's published from is deleted along with the rest of the output
+    doxidize::ops::clean(&config, &log).expect("clean failed");
+
+    OpenOptions::new()
+        .append(true)
+        .open(dir_path.join("docs").join("README.md"))
+        .and_then(|mut file| file.write_all(b"\nSomething new.\n"))
+        .expect("could not add to README");
+
+    doxidize::ops::build(&config, &log).expect("build failed");
+    doxidize::ops::publish(&config, &log).expect("publish after clean failed");
+
+    assert_eq!(git(&origin, &["rev-list", "--count", "gh-pages"]), "2");
+}
@@ -0,0 +1,342 @@
+extern crate doxidize;
+
+#[macro_use]
+extern crate slog;
+extern crate slog_async;
+extern crate slog_term;
+
+extern crate tempdir;
+
+use doxidize::ops::UpdateOptions;
+use doxidize::Config;
+use tempdir::TempDir;
+
+use std::fs::{File, OpenOptions};
+use std::io::prelude::*;
+
+mod util;
+
+fn read(path: &::std::path::Path) -> String {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .expect("could not read file");
+    contents
+}
+
+#[test]
+fn update_keeps_hand_written_prose() {
+    let dir = TempDir::new("update_keeps_prose").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    let dir_path = dir.path();
+
+    util::cargo_init(dir_path).expect("Could not create sample crate");
+
+    let config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    doxidize::ops::init(&config, &log).expect("init failed");
+
+    let readme_path = dir_path.join("docs").join("api").join("README.md");
+
+    OpenOptions::new()
+        .append(true)
+        .open(&readme_path)
+        .and_then(|mut file| file.write_all(b"\n## Getting started\n\nWritten by hand.\n"))
+        .expect("could not add to api README");
+
+    doxidize::ops::update(&config, &log, &UpdateOptions::default()).expect("update failed");
+
+    let readme = read(&readme_path);
+    assert!(readme.contains("# `example`"), "{}", readme);
+    assert!(readme.contains("Written by hand."), "{}", readme);
+}
+
+#[test]
+fn update_reports_hand_edited_generated_sections() {
+    let dir = TempDir::new("update_conflicts").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    let dir_path = dir.path();
+
+    util::cargo_init(dir_path).expect("Could not create sample crate");
+
+    let config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    doxidize::ops::init(&config, &log).expect("init failed");
+
+    let readme_path = dir_path.join("docs").join("api").join("README.md");
+    let edited = read(&readme_path).replace("# `example`", "# The example crate");
+
+    File::create(&readme_path)
+        .and_then(|mut file| file.write_all(edited.as_bytes()))
+        .expect("could not edit api README");
+
+    let error = doxidize::ops::update(&config, &log, &UpdateOptions::default())
+        .expect_err("update should have failed");
+    let message = error.to_string();
+
+    assert!(message.contains("README.md"), "{}", message);
+    assert!(message.contains("edited by hand"), "{}", message);
+
+    // the edit is left alone
+    assert_eq!(read(&readme_path), edited);
+}
+
+#[test]
+fn update_only_leaves_hand_edited_items_alone() {
+    let dir = TempDir::new("update_item_conflicts").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    let dir_path = dir.path();
+
+    util::cargo_init(dir_path).expect("Could not create sample crate");
+
+    let main_path = dir_path.join("src").join("main.rs");
+    let write_main = |docs: &str| {
+        let main = format!(
+            "pub struct Point;\n\nimpl Point {{\n    pub fn a(&self) {{}}\n\n    /// {}\n    \
+             pub fn b(&self) {{}}\n}}\n\nfn main() {{}}\n",
+            docs
+        );
+
+        File::create(&main_path)
+            .and_then(|mut file| file.write_all(main.as_bytes()))
+            .expect("could not write main.rs");
+    };
+
+    write_main("Old docs.");
+
+    let config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    doxidize::ops::init(&config, &log).expect("init failed");
+
+    let point_path = dir_path.join("docs").join("api").join("Point.md");
+    let edited = read(&point_path)
+        .replace("### method `a`", "### method `a`, edited")
+        .replace(
+            "<!-- doxidize:generated ",
+            "Written by hand.\n\n<!-- doxidize:generated ",
+        );
+
+    File::create(&point_path)
+        .and_then(|mut file| file.write_all(edited.as_bytes()))
+        .expect("could not edit Point.md");
+
+    write_main("New docs.");
+
+    let error = doxidize::ops::update(&config, &log, &UpdateOptions::default())
+        .expect_err("update should have failed");
+    let message = error.to_string();
+
+    assert!(message.contains("1 generated section(s)"), "{}", message);
+    assert!(message.contains("`method a`"), "{}", message);
+
+    // the rest of the page is still updated
+    let point = read(&point_path);
+    assert!(point.contains("### method `a`, edited"), "{}", point);
+    assert!(point.contains("New docs."), "{}", point);
+    assert!(!point.contains("Old docs."), "{}", point);
+    assert!(point.contains("Written by hand."), "{}", point);
+}
+
+#[test]
+fn dry_run_reports_changes_without_making_them() {
+    let dir = TempDir::new("update_dry_run").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    let dir_path = dir.path();
+
+    util::cargo_init(dir_path).expect("Could not create sample crate");
+
+    let config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    doxidize::ops::init(&config, &log).expect("init failed");
+
+    let dry_run = UpdateOptions {
+        dry_run: true,
+        ..UpdateOptions::default()
+    };
+
+    doxidize::ops::update(&config, &log, &dry_run).expect("nothing should have changed");
+
+    let main_path = dir_path.join("src").join("main.rs");
+    let main = read(&main_path);
+
+    File::create(&main_path)
+        .and_then(|mut file| write!(file, "//! Some new docs.\n\n{}", main))
+        .expect("could not add docs");
+
+    let readme_path = dir_path.join("docs").join("api").join("README.md");
+    let readme = read(&readme_path);
+
+    let error = doxidize::ops::update(&config, &log, &dry_run)
+        .expect_err("the api docs should be out of date");
+
+    assert!(error.to_string().contains("out of date"), "{}", error);
+    assert_eq!(read(&readme_path), readme);
+}
+
+#[test]
+fn dry_run_counts_orphan_directories() {
+    let dir = TempDir::new("update_dry_run_dirs").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    let dir_path = dir.path();
+
+    util::cargo_init(dir_path).expect("Could not create sample crate");
+
+    let config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    doxidize::ops::init(&config, &log).expect("init failed");
+
+    let orphan = dir_path.join("docs").join("api").join("removed");
+    ::std::fs::create_dir(&orphan).expect("could not create directory");
+
+    let dry_run = UpdateOptions {
+        dry_run: true,
+        ..UpdateOptions::default()
+    };
+
+    let error = doxidize::ops::update(&config, &log, &dry_run)
+        .expect_err("the directory should be deleted");
+
+    assert!(error.to_string().contains("out of date"), "{}", error);
+    assert!(orphan.is_dir());
+}
+
+/// Take the markers out of a generated file, as if it had been generated before there were any.
+fn remove_markers(path: &::std::path::Path) -> String {
+    let unmarked: Vec<_> = read(path)
+        .lines()
+        .filter(|line| !line.starts_with("<!-- doxidize:"))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    let unmarked = unmarked.concat();
+
+    File::create(path)
+        .and_then(|mut file| file.write_all(unmarked.as_bytes()))
+        .expect("could not remove markers");
+
+    unmarked
+}
+
+#[test]
+fn update_adopts_files_from_before_the_markers() {
+    let dir = TempDir::new("update_adopts").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    let dir_path = dir.path();
+
+    util::cargo_init(dir_path).expect("Could not create sample crate");
+
+    let config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    doxidize::ops::init(&config, &log).expect("init failed");
+
+    let readme_path = dir_path.join("docs").join("api").join("README.md");
+    let generated = read(&readme_path);
+
+    remove_markers(&readme_path);
+
+    doxidize::ops::update(&config, &log, &UpdateOptions::default()).expect("update failed");
+
+    assert_eq!(read(&readme_path), generated);
+}
+
+#[test]
+fn update_adopts_edited_files_when_asked_to() {
+    let dir = TempDir::new("update_adopt_option").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    let dir_path = dir.path();
+
+    util::cargo_init(dir_path).expect("Could not create sample crate");
+
+    let config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    doxidize::ops::init(&config, &log).expect("init failed");
+
+    let readme_path = dir_path.join("docs").join("api").join("README.md");
+    let generated = read(&readme_path);
+
+    let edited = format!("{}\nAn old template.\n", remove_markers(&readme_path));
+    File::create(&readme_path)
+        .and_then(|mut file| file.write_all(edited.as_bytes()))
+        .expect("could not edit api README");
+
+    let error = doxidize::ops::update(&config, &log, &UpdateOptions::default())
+        .expect_err("update should have failed");
+    assert!(error.to_string().contains("--adopt"), "{}", error);
+    assert_eq!(read(&readme_path), edited);
+
+    let adopt = UpdateOptions {
+        adopt: true,
+        ..UpdateOptions::default()
+    };
+
+    doxidize::ops::update(&config, &log, &adopt).expect("update failed");
+
+    assert_eq!(read(&readme_path), generated);
+}
+
+#[test]
+fn update_adds_new_examples() {
+    let dir = TempDir::new("update_examples").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    let dir_path = dir.path();
+
+    util::cargo_init(dir_path).expect("Could not create sample crate");
+
+    let config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    doxidize::ops::init(&config, &log).expect("init failed");
+
+    ::std::fs::create_dir_all(dir_path.join("examples")).expect("could not create examples dir");
+    File::create(dir_path.join("examples").join("new-thing.rs"))
+        .and_then(|mut file| file.write_all(b"fn main() {\n    println!(\"new\");\n}\n"))
+        .expect("could not write example");
+
+    doxidize::ops::update(&config, &log, &UpdateOptions::default()).expect("update failed");
+
+    let page = read(&dir_path.join("docs").join("examples").join("new-thing.md"));
+    assert!(page.starts_with("---\nid = \"new-thing.rs\""), "{}", page);
+    assert!(page.contains("println!(\"new\");"), "{}", page);
+
+    // the rest of the menu is left alone
+    let menu = read(&dir_path.join("docs").join("Menu.toml"));
+    assert!(menu.starts_with("\"Getting Started\" = [\n    \"overview\",\n]\n"), "{}", menu);
+    assert!(menu.contains("\"new-thing.rs\""), "{}", menu);
+
+    // the build fails if the menu lists a page that doesn't exist
+    doxidize::ops::build(&config, &log).expect("build failed");
+}
+
+#[test]
+fn update_adopts_example_pages_from_before_the_markers() {
+    let dir = TempDir::new("update_adopts_examples").expect("could not generate temp dir");
+    let log = util::make_logger();
+
+    let dir_path = dir.path();
+
+    util::cargo_init(dir_path).expect("Could not create sample crate");
+
+    ::std::fs::create_dir_all(dir_path.join("examples")).expect("could not create examples dir");
+    File::create(dir_path.join("examples").join("old-thing.rs"))
+        .and_then(|mut file| file.write_all(b"fn main() {\n    println!(\"old\");\n}\n"))
+        .expect("could not write example");
+
+    let config = Config::new(dir_path.join("Cargo.toml")).unwrap();
+
+    doxidize::ops::init(&config, &log).expect("init failed");
+
+    let page_path = dir_path.join("docs").join("examples").join("old-thing.md");
+    let generated = read(&page_path);
+
+    remove_markers(&page_path);
+
+    doxidize::ops::update(&config, &log, &UpdateOptions::default()).expect("update failed");
+
+    assert_eq!(read(&page_path), generated);
+}